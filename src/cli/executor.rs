@@ -7,7 +7,27 @@ use miette::Result;
 pub async fn execute(options: ValidatedOptions) -> Result<()> {
     match options {
         ValidatedOptions::Init {} => commands::init::execute().await,
-        ValidatedOptions::Watch {} => commands::watch::execute().await,
-        ValidatedOptions::None => commands::watch::execute().await,
+        ValidatedOptions::Explain { packages, no_cache } => {
+            commands::explain::execute(packages, no_cache).await
+        }
+        ValidatedOptions::Watch {
+            auto_apply,
+            packages,
+            no_cache,
+        } => commands::watch::execute(auto_apply, packages, no_cache).await,
+        ValidatedOptions::Test {
+            auto_apply,
+            packages,
+            no_cache,
+            filters,
+        } => commands::test::execute(auto_apply, packages, no_cache, filters).await,
+        ValidatedOptions::Run {
+            auto_apply,
+            packages,
+            no_cache,
+            args,
+        } => commands::run::execute(auto_apply, packages, no_cache, args).await,
+        ValidatedOptions::Undo { id, last, list } => commands::undo::execute(id, last, list).await,
+        ValidatedOptions::None => commands::watch::execute(false, Vec::new(), false).await,
     }
 }
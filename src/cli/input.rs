@@ -50,7 +50,7 @@ impl<'a> TermThemeRenderer<'a> {
         f: F,
     ) -> io::Result<()> {
         let mut buf = String::new();
-        f(self, &mut buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        f(self, &mut buf).map_err(io::Error::other)?;
         self.height += buf.chars().filter(|&x| x == '\n').count();
         self.term.write_str(&buf)
     }
@@ -62,7 +62,7 @@ impl<'a> TermThemeRenderer<'a> {
         f: F,
     ) -> io::Result<()> {
         let mut buf = String::new();
-        f(self, &mut buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        f(self, &mut buf).map_err(io::Error::other)?;
         self.height += buf.chars().filter(|&x| x == '\n').count() + 1;
         self.term.write_line(&buf)
     }
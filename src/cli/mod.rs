@@ -2,4 +2,6 @@ pub mod executor;
 pub mod input;
 pub mod parser;
 pub mod prompts;
+pub mod report;
+pub mod stream;
 pub mod validator;
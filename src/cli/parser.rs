@@ -63,12 +63,8 @@ pub async fn parse() -> Result<()> {
             } else {
                 options.insert(flag.to_string(), None);
             }
-        } else {
-            if index > 1 {
-                options.insert(arg.to_string(), None);
-            } else if command == "watch" && index == 1 {
-                options.insert(arg.to_string(), None);
-            }
+        } else if index > 1 || (command == "watch" && index == 1) {
+            options.insert(arg.to_string(), None);
         }
     }
 
@@ -92,8 +92,8 @@ impl Input<'_> {
             .with_prompt(self.message.clone())
             .allow_empty(self.allow_empty);
 
-        if self.default.is_some() {
-            input.default(self.default.as_ref().unwrap().to_string());
+        if let Some(default) = &self.default {
+            input.default(default.to_string());
         }
 
         let value = input.interact_text()?;
@@ -189,10 +189,29 @@ impl<'i> Select<'i> {
             .with_prompt(self.message.clone())
             //.paged(self.paged)
             .items(&self.items);
-        if self.selected.is_some() {
-            input.default(self.selected.unwrap() - 1);
+        if let Some(selected) = self.selected {
+            input.default(selected - 1);
         }
 
         input.interact()
     }
 }
+
+/// Prompt that opens the user's editor and returns the edited text
+#[derive(Debug)]
+pub struct Editor<'i> {
+    /// Text the editor is opened with
+    pub initial: Cow<'i, str>,
+
+    /// Extension of the temporary file, lets the editor pick the right syntax highlighting
+    pub extension: Cow<'i, str>,
+}
+
+impl Editor<'_> {
+    /// Returns `None` if the editor was closed without saving.
+    pub fn run(&self) -> Result<Option<String>> {
+        dialoguer::Editor::new()
+            .extension(&self.extension)
+            .edit(&self.initial)
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+use linked_hash_map::LinkedHashMap;
+use miette::{
+    GraphicalReportHandler, LabeledSpan, MietteDiagnostic, NamedSource, Report, Severity,
+};
+
+use crate::{
+    diagnostics::diagnostic::{Diagnostic, Level, Span},
+    fixer::explain::Explanation,
+};
+
+/// Prints related diagnostics with the code they point at, followed by the model's explanation of them
+pub fn show_explanation(diagnostics: &[&Diagnostic], explanation: &Explanation) {
+    let mut sources: HashMap<String, Option<String>> = HashMap::new();
+
+    for diagnostic in diagnostics {
+        for report in reports(diagnostic, &mut sources) {
+            let mut rendered = String::new();
+
+            if GraphicalReportHandler::new()
+                .render_report(&mut rendered, report.as_ref())
+                .is_ok()
+            {
+                print!("{}", rendered);
+            }
+        }
+    }
+
+    for (title, text) in [
+        ("💡 Explanation", &explanation.explanation),
+        ("🔍 Root cause", &explanation.root_cause),
+        ("🔧 Suggested fix", &explanation.suggested_fix),
+    ] {
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        println!("{}", title.bold());
+
+        for line in text.trim().lines() {
+            println!("  {}", line);
+        }
+
+        println!();
+    }
+}
+
+// One report per file the diagnostic points into, the file with the primary span first
+fn reports(diagnostic: &Diagnostic, sources: &mut HashMap<String, Option<String>>) -> Vec<Report> {
+    let mut files: LinkedHashMap<String, Vec<&Span>> = LinkedHashMap::new();
+
+    if let Some(primary) = diagnostic.primary_span() {
+        files.insert(primary.invocation().file_name.clone(), Vec::new());
+    }
+
+    for span in &diagnostic.spans {
+        // Spans inside macros point at the macro's definition, the user's code is where it was invoked
+        let span = span.invocation();
+
+        files.entry(span.file_name.clone()).or_default().push(span);
+    }
+
+    let severity = match diagnostic.level {
+        Level::Warning => Severity::Warning,
+        Level::Note | Level::Help => Severity::Advice,
+        _ => Severity::Error,
+    };
+
+    let mut reports = Vec::new();
+
+    for (index, (file, spans)) in files.into_iter().enumerate() {
+        let source = sources
+            .entry(file.clone())
+            .or_insert_with(|| std::fs::read_to_string(&file).ok());
+
+        let Some(source) = source.clone() else {
+            continue;
+        };
+
+        let labels: Vec<LabeledSpan> = spans
+            .iter()
+            .filter_map(|span| {
+                let start = offset(&source, span.line_start, span.column_start)?;
+                let end = offset(&source, span.line_end, span.column_end).unwrap_or(start);
+
+                Some(LabeledSpan::new(
+                    span.label
+                        .clone()
+                        .or_else(|| span.is_primary.then(|| String::from("here"))),
+                    start,
+                    end.saturating_sub(start).max(1),
+                ))
+            })
+            .collect();
+
+        // Only the first report carries the message, the others show where else it points
+        let message = if index == 0 {
+            diagnostic.message.clone()
+        } else {
+            format!("...related code in {}", file)
+        };
+
+        let mut report = MietteDiagnostic::new(message)
+            .with_severity(severity)
+            .with_labels(labels);
+
+        if let Some(code) = diagnostic.code.as_ref().filter(|_| index == 0) {
+            report = report.with_code(&code.code);
+        }
+
+        if index == 0 {
+            let notes: Vec<String> = diagnostic
+                .children
+                .iter()
+                .filter(|child| child.spans.is_empty())
+                .map(|child| format!("{}: {}", child.level, child.message))
+                .collect();
+
+            if !notes.is_empty() {
+                report = report.with_help(notes.join("\n"));
+            }
+        }
+
+        reports.push(Report::new(report).with_source_code(NamedSource::new(&file, source)));
+    }
+
+    // Nothing to point at, the message is still worth showing
+    if reports.is_empty() {
+        println!("{}", diagnostic.rendered().bright_red());
+    }
+
+    reports
+}
+
+// The byte offset of a 1-based line and character column in `source`
+fn offset(source: &str, line: usize, column: usize) -> Option<usize> {
+    let mut start = 0;
+
+    for (index, text) in source.split_inclusive('\n').enumerate() {
+        if index + 1 == line {
+            let column_offset = text
+                .char_indices()
+                .nth(column.saturating_sub(1))
+                .map(|(offset, _)| offset)
+                .unwrap_or(text.trim_end_matches('\n').len());
+
+            return Some(start + column_offset);
+        }
+
+        start += text.len();
+    }
+
+    None
+}
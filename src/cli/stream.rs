@@ -0,0 +1,252 @@
+use std::{
+    io::{IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    terminal::{self, Clear, ClearType},
+    QueueableCommand,
+};
+use tokio::sync::oneshot;
+
+use crate::models::pricing::Price;
+
+// Same frames as indicatif's default spinner
+const FRAMES: [char; 8] = ['⠁', '⠂', '⠄', '⡀', '⢀', '⠠', '⠐', '⠈'];
+
+/// Shows a completion while it streams in.
+///
+/// Finished lines are printed as they arrive, and a status line below them shows
+/// how fast tokens come in, what the request has cost so far and how to cancel it.
+pub struct StreamView {
+    status: String,
+    price: Price,
+    prompt_tokens: usize,
+    started: Instant,
+
+    /// The line that is still being written
+    line: String,
+
+    /// Tokens received so far, as counted by the provider's tokenizer
+    tokens: usize,
+    frame: usize,
+
+    // Redrawing the status line only makes sense on a terminal
+    interactive: bool,
+}
+
+impl StreamView {
+    pub fn new(status: String, price: Price, prompt_tokens: usize) -> Self {
+        Self {
+            status,
+            price,
+            prompt_tokens,
+            started: Instant::now(),
+            line: String::new(),
+            tokens: 0,
+            frame: 0,
+            interactive: std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Adds a chunk of the completion that is `tokens` tokens long.
+    ///
+    /// A chunk can hold any number of tokens, so they have to be counted by the caller.
+    pub fn push(&mut self, chunk: &str, tokens: usize) {
+        self.tokens += tokens;
+
+        for character in chunk.chars() {
+            match character {
+                '\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    self.print_line(&line);
+                }
+                '\r' => {}
+                _ => self.line.push(character),
+            }
+        }
+
+        self.draw();
+    }
+
+    /// Advances the spinner, so it keeps moving while the model is thinking
+    pub fn tick(&mut self) {
+        self.frame = (self.frame + 1) % FRAMES.len();
+        self.draw();
+    }
+
+    /// Prints what is left of the completion and removes the status line
+    pub fn finish(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.print_line(&line);
+        }
+
+        self.clear_status();
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+
+        if elapsed > 0.0 {
+            self.tokens as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn print_line(&self, line: &str) {
+        self.clear_status();
+
+        // Raw mode doesn't return the carriage on a newline, so it is done by hand
+        print!("{}\r\n", line.bright_black());
+
+        self.draw();
+    }
+
+    fn clear_status(&self) {
+        if !self.interactive {
+            return;
+        }
+
+        let mut stdout = std::io::stdout();
+        let _ = stdout.queue(Clear(ClearType::CurrentLine));
+        let _ = write!(stdout, "\r");
+        let _ = stdout.flush();
+    }
+
+    fn draw(&self) {
+        if !self.interactive {
+            return;
+        }
+
+        let status = format!(
+            "{} {} {} tokens · {:.1} tok/s · {:.4}$ · esc to cancel",
+            FRAMES[self.frame],
+            self.status,
+            self.tokens,
+            self.tokens_per_second(),
+            self.price.cost(self.prompt_tokens, self.tokens)
+        );
+
+        // The tail of the line being written fills whatever room is left
+        let width = terminal::size()
+            .map(|(width, _)| width as usize)
+            .unwrap_or(80);
+        let room = width.saturating_sub(status.chars().count() + 4);
+
+        let tail: String = {
+            let characters: Vec<char> = self.line.chars().collect();
+            characters[characters.len().saturating_sub(room)..]
+                .iter()
+                .collect()
+        };
+
+        let mut stdout = std::io::stdout();
+        let _ = stdout.queue(Clear(ClearType::CurrentLine));
+        let _ = write!(
+            stdout,
+            "\r{}{}",
+            status,
+            if room > 0 && !tail.is_empty() {
+                format!(" · {}", tail.bright_black())
+            } else {
+                String::new()
+            }
+        );
+        let _ = stdout.flush();
+    }
+}
+
+/// Listens for Esc, `q` or Ctrl+C on another thread while a request is running.
+///
+/// The terminal is put in raw mode so single key presses can be read, until `stop` is called.
+pub struct CancelListener {
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    raw: bool,
+}
+
+impl CancelListener {
+    /// Starts listening, the receiver resolves when the user asks to cancel
+    pub fn start() -> (Self, oneshot::Receiver<()>) {
+        let (sender, receiver) = oneshot::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        // Without a terminal there's nobody to press a key
+        let raw = std::io::stdin().is_terminal() && terminal::enable_raw_mode().is_ok();
+
+        if !raw {
+            return (
+                Self {
+                    stopped,
+                    handle: None,
+                    raw,
+                },
+                receiver,
+            );
+        }
+
+        let handle = std::thread::spawn({
+            let stopped = stopped.clone();
+
+            move || {
+                // Polling with a timeout lets the thread notice it was stopped, so it doesn't eat the next key press
+                while !stopped.load(Ordering::SeqCst) {
+                    if !event::poll(Duration::from_millis(50)).unwrap_or(false) {
+                        continue;
+                    }
+
+                    if let Ok(Event::Key(key)) = event::read() {
+                        if is_cancel(&key) {
+                            let _ = sender.send(());
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        (
+            Self {
+                stopped,
+                handle: Some(handle),
+                raw,
+            },
+            receiver,
+        )
+    }
+
+    /// Stops listening and gives the terminal back
+    pub fn stop(self) {}
+}
+
+// Also runs if the request panics, so the terminal is never left in raw mode
+impl Drop for CancelListener {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+
+        if self.raw {
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+}
+
+fn is_cancel(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
@@ -3,18 +3,128 @@ use linked_hash_map::LinkedHashMap;
 #[derive(Debug, Clone)]
 pub enum ValidatedOptions {
     Init {},
-    Watch {},
+    Explain {
+        packages: Vec<String>,
+        no_cache: bool,
+    },
+    Watch {
+        auto_apply: bool,
+        packages: Vec<String>,
+        no_cache: bool,
+    },
+    Test {
+        auto_apply: bool,
+        packages: Vec<String>,
+        no_cache: bool,
+        filters: Vec<String>,
+    },
+    Run {
+        auto_apply: bool,
+        packages: Vec<String>,
+        no_cache: bool,
+        args: Vec<String>,
+    },
+    Undo {
+        id: Option<String>,
+        last: Option<usize>,
+        list: bool,
+    },
     None,
 }
 
+const PACKAGE_FLAGS: [&str; 2] = ["-p", "--package"];
+
+// Flags that take a value, so the value isn't mistaken for a positional argument
+const VALUE_FLAGS: [&str; 4] = ["-p", "--package", "-n", "--last"];
+
+// Values of `-p`/`--package`, which can be given several times, unlike other flags
+fn packages(args: &[String]) -> Vec<String> {
+    let mut packages = Vec::new();
+
+    for (index, arg) in args.iter().enumerate() {
+        if let Some(package) = arg.strip_prefix("--package=") {
+            packages.push(package.to_string());
+        } else if PACKAGE_FLAGS.contains(&arg.as_str()) {
+            if let Some(package) = args.get(index + 1) {
+                packages.push(package.clone());
+            }
+        }
+    }
+
+    packages
+}
+
+// Arguments that are neither flags nor the value of one
+fn positional(args: &[String]) -> Vec<String> {
+    args.iter()
+        .enumerate()
+        .filter(|(index, arg)| {
+            !arg.starts_with('-')
+                && !index
+                    .checked_sub(1)
+                    .is_some_and(|previous| VALUE_FLAGS.contains(&args[previous].as_str()))
+        })
+        .map(|(_, arg)| arg.clone())
+        .collect()
+}
+
 pub fn validate(
     command: &str,
-    _options: LinkedHashMap<String, Option<String>>,
-    _raw_args: Option<Vec<String>>,
+    options: LinkedHashMap<String, Option<String>>,
+    raw_args: Option<Vec<String>>,
 ) -> ValidatedOptions {
     match command {
         "init" => ValidatedOptions::Init {},
-        "watch" => ValidatedOptions::Watch {},
-        _ => ValidatedOptions::Watch {},
+        "explain" => ValidatedOptions::Explain {
+            packages: packages(&raw_args.unwrap_or_default()),
+            no_cache: options.contains_key("no-cache"),
+        },
+        "run" => {
+            let raw_args = raw_args.unwrap_or_default();
+
+            // Everything after `--` belongs to the program, so its flags aren't mistaken for ours
+            let (own, program) = match raw_args.iter().position(|arg| arg == "--") {
+                Some(index) => (&raw_args[..index], raw_args[index + 1..].to_vec()),
+                None => (&raw_args[..], Vec::new()),
+            };
+
+            ValidatedOptions::Run {
+                auto_apply: own.iter().any(|arg| arg == "--yes" || arg == "-y"),
+                packages: packages(own),
+                no_cache: own.iter().any(|arg| arg == "--no-cache"),
+                args: program,
+            }
+        }
+        "test" => {
+            let raw_args = raw_args.unwrap_or_default();
+
+            ValidatedOptions::Test {
+                auto_apply: options.contains_key("yes") || options.contains_key("y"),
+                packages: packages(&raw_args),
+                no_cache: options.contains_key("no-cache"),
+                // Anything after `test` that isn't a flag is the name of a test to run
+                filters: positional(&raw_args).into_iter().skip(1).collect(),
+            }
+        }
+        "undo" => {
+            let raw_args = raw_args.unwrap_or_default();
+
+            ValidatedOptions::Undo {
+                // Anything after `undo` that isn't a flag is the id of the fix to undo
+                id: positional(&raw_args).into_iter().nth(1),
+                last: options
+                    .get("last")
+                    .or_else(|| options.get("n"))
+                    .cloned()
+                    .flatten()
+                    .and_then(|count| count.parse().ok()),
+                list: options.contains_key("list"),
+            }
+        }
+        _ => ValidatedOptions::Watch {
+            auto_apply: options.contains_key("yes") || options.contains_key("y"),
+            packages: packages(&raw_args.unwrap_or_default()),
+            no_cache: options.contains_key("no-cache"),
+        },
     }
 }
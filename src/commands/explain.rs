@@ -0,0 +1,36 @@
+use miette::Result;
+
+use crate::{
+    config::Config, diagnostics::check::check_with_spinner, fixer::explain::explain_errors,
+    providers, workspace::Workspace,
+};
+
+pub async fn execute(packages: Vec<String>, no_cache: bool) -> Result<()> {
+    println!("⭐ Neura has joined your session.");
+
+    let mut config = Config::load();
+
+    // Packages passed in take priority over `neura.toml`
+    if !packages.is_empty() {
+        config.packages = packages;
+    }
+
+    if no_cache {
+        config.cache.enabled = false;
+    }
+
+    config.validate_packages(Workspace::current())?;
+
+    let provider = providers::from_config(&config)?;
+
+    let errors = check_with_spinner(&config);
+
+    if errors.is_empty() {
+        println!("✅ There's nothing to explain, the crate builds without errors.");
+        return Ok(());
+    }
+
+    explain_errors(&config, provider.as_ref(), &errors).await;
+
+    Ok(())
+}
@@ -1,2 +1,6 @@
+pub mod explain;
 pub mod init;
+pub mod run;
+pub mod test;
+pub mod undo;
 pub mod watch;
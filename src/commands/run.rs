@@ -0,0 +1,123 @@
+use colored::Colorize;
+use miette::Result;
+
+use crate::{
+    config::Config,
+    diagnostics::{
+        check::{check_with_spinner, spawn_check},
+        diagnostic::Level,
+        run::spawn_run,
+    },
+    fixer::repair::fix_errors,
+    patch::git,
+    providers,
+    workspace::Workspace,
+};
+
+pub async fn execute(
+    auto_apply: bool,
+    packages: Vec<String>,
+    no_cache: bool,
+    args: Vec<String>,
+) -> Result<()> {
+    println!("⭐ Neura has joined your session.");
+
+    let mut config = Config::load();
+
+    // Flags passed in take priority over `neura.toml`
+    config.auto_apply |= auto_apply;
+
+    if !packages.is_empty() {
+        config.packages = packages;
+    }
+
+    if no_cache {
+        config.cache.enabled = false;
+    }
+
+    config.validate_packages(Workspace::current())?;
+
+    // Before anything is edited
+    git::prepare(&config.git)?;
+
+    let provider = providers::from_config(&config)?;
+
+    // The program can't run until it builds, so build errors are fixed on their own first
+    let build_errors = check_with_spinner(&config);
+
+    if build_errors.iter().any(|error| error.level == Level::Error) {
+        let check = || spawn_check(&config);
+
+        let remaining = fix_errors(
+            &config,
+            provider.as_ref(),
+            &build_errors,
+            &build_errors,
+            &check,
+        )
+        .await;
+
+        if remaining.iter().any(|error| error.level == Level::Error) {
+            println!("❌ The program still doesn't build.");
+            return Ok(());
+        }
+    }
+
+    println!(
+        "🚀 Running `{}` ...",
+        format!("cargo run -- {}", args.join(" "))
+            .trim_end()
+            .bright_cyan()
+    );
+
+    let run = spawn_run(&args, &config);
+
+    let Some(panic) = &run.panic else {
+        match run.status {
+            Some(0) => println!("✅ The program exited without panicking."),
+            Some(code) => println!(
+                "{} The program exited with status {}, but didn't panic, so there's nothing to debug.",
+                ">".bright_black(),
+                code.to_string().bright_red()
+            ),
+            None => println!(
+                "{} The program was stopped before it finished.",
+                ">".bright_black()
+            ),
+        }
+
+        return Ok(());
+    };
+
+    let Some(diagnostic) = run.panic_diagnostic() else {
+        println!(
+            "⚠️  The program panicked outside of the crate's sources, there's no code to fix."
+        );
+        return Ok(());
+    };
+
+    println!(
+        "🔎 The program panicked in {}: {}",
+        diagnostic.file().unwrap_or_default().bright_yellow(),
+        panic
+            .message
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .bright_red()
+    );
+
+    // The program is run again with the same arguments to verify each fix
+    let check = || spawn_run(&args, &config).diagnostics();
+
+    let errors = vec![diagnostic];
+    let remaining = fix_errors(&config, provider.as_ref(), &errors, &errors, &check).await;
+
+    if remaining.iter().any(|error| error.is_same(&errors[0])) {
+        println!("❌ The program still panics.");
+    } else {
+        println!("✅ The program no longer panics there.");
+    }
+
+    Ok(())
+}
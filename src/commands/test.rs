@@ -0,0 +1,142 @@
+use colored::Colorize;
+use miette::Result;
+
+use crate::{
+    config::Config,
+    diagnostics::{
+        check::spawn_cargo,
+        diagnostic::{Diagnostic, Level},
+        test::{spawn_tests, tests_with_spinner},
+    },
+    fixer::repair::fix_errors,
+    patch::git,
+    providers,
+    workspace::Workspace,
+};
+
+pub async fn execute(
+    auto_apply: bool,
+    packages: Vec<String>,
+    no_cache: bool,
+    filters: Vec<String>,
+) -> Result<()> {
+    println!("⭐ Neura has joined your session.");
+
+    let mut config = Config::load();
+
+    // Flags passed in take priority over `neura.toml`
+    config.auto_apply |= auto_apply;
+
+    if !packages.is_empty() {
+        config.packages = packages;
+    }
+
+    if no_cache {
+        config.cache.enabled = false;
+    }
+
+    config.validate_packages(Workspace::current())?;
+
+    // Before anything is edited
+    git::prepare(&config.git)?;
+
+    let provider = providers::from_config(&config)?;
+
+    let mut run = tests_with_spinner(&filters, &config);
+
+    // Tests can't run until they build, so build errors are fixed on their own first
+    if run
+        .build_errors
+        .iter()
+        .any(|error| error.level == Level::Error)
+    {
+        println!(
+            "🔎 The tests don't build, found {} {}.",
+            run.build_errors.len().to_string().bright_red(),
+            if run.build_errors.len() == 1 {
+                "error"
+            } else {
+                "errors"
+            }
+        );
+
+        let check = || spawn_cargo(&["test", "--no-run"], &config);
+
+        let remaining = fix_errors(
+            &config,
+            provider.as_ref(),
+            &run.build_errors,
+            &run.build_errors,
+            &check,
+        )
+        .await;
+
+        if remaining.iter().any(|error| error.level == Level::Error) {
+            println!("❌ The tests still don't build.");
+            return Ok(());
+        }
+
+        run = tests_with_spinner(&filters, &config);
+    }
+
+    if run.failures.is_empty() {
+        println!("✅ All tests passed.");
+        return Ok(());
+    }
+
+    println!(
+        "🔎 Found {} failing {}:",
+        run.failures.len().to_string().bright_red(),
+        if run.failures.len() == 1 {
+            "test"
+        } else {
+            "tests"
+        }
+    );
+
+    for failure in &run.failures {
+        println!("{} {}", ">".bright_black(), failure.name);
+    }
+
+    for failure in run.untraceable() {
+        println!(
+            "⚠️  Skipping {}, it can't be found in the crate's sources.",
+            failure.name.bright_red()
+        );
+    }
+
+    // Only the tests that failed are run again to verify each fix
+    let failing: Vec<String> = run
+        .failures
+        .iter()
+        .map(|failure| failure.name.clone())
+        .collect();
+
+    let check = || spawn_tests(&failing, &config).diagnostics();
+
+    let errors = run.diagnostics();
+    let remaining = fix_errors(&config, provider.as_ref(), &errors, &errors, &check).await;
+
+    let still_failing = remaining
+        .iter()
+        .filter(|error| failing.iter().any(|name| is_failure_of(error, name)))
+        .count()
+        + run.untraceable().len();
+
+    if still_failing == 0 {
+        println!("✅ All failing tests pass now.");
+    } else {
+        println!(
+            "📊 {} of {} failing tests pass now.",
+            (failing.len() - still_failing).to_string().bright_green(),
+            failing.len()
+        );
+    }
+
+    Ok(())
+}
+
+// Whether the diagnostic reports `name` failing
+fn is_failure_of(error: &Diagnostic, name: &str) -> bool {
+    error.message == format!("test `{}` failed", name)
+}
@@ -0,0 +1,128 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use colored::Colorize;
+use miette::Result;
+
+use crate::patch::journal::{Entry, Journal};
+
+pub async fn execute(id: Option<String>, last: Option<usize>, list: bool) -> Result<()> {
+    let mut journal = Journal::load();
+
+    if list {
+        show(&journal);
+        return Ok(());
+    }
+
+    let ids: Vec<String> = match &id {
+        Some(id) => {
+            let Some(entry) = journal.find(id) else {
+                return Err(miette::miette!(
+                    "there's no fix with the id `{}` in the journal, `neura undo --list` shows them all",
+                    id
+                ));
+            };
+
+            if entry.undone {
+                println!(
+                    "{} {} has been undone already.",
+                    ">".bright_black(),
+                    entry.id
+                );
+                return Ok(());
+            }
+
+            vec![entry.id.clone()]
+        }
+        None => journal
+            .last(last.unwrap_or(1))
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect(),
+    };
+
+    if ids.is_empty() {
+        println!("✅ There's nothing to undo.");
+        return Ok(());
+    }
+
+    // Newest first, so every fix finds its files the way it left them
+    for id in ids {
+        let Some(entry) = journal.find(&id).cloned() else {
+            continue;
+        };
+
+        if let Err(err) = entry.undo() {
+            return Err(miette::miette!(
+                "couldn't undo {}: {}, so it was left as it is along with every fix before it",
+                entry.id,
+                err
+            ));
+        }
+
+        journal.mark_undone(&entry.id);
+        journal.save().map_err(|err| {
+            miette::miette!(
+                "undid {} but couldn't update the journal: {}",
+                entry.id,
+                err
+            )
+        })?;
+
+        println!(
+            "↩️  Undid {} {} in {}.",
+            entry.id.bright_cyan(),
+            entry.headline().bright_red(),
+            files(&entry)
+        );
+    }
+
+    Ok(())
+}
+
+// Prints every fix in the journal, newest first
+fn show(journal: &Journal) {
+    if journal.entries.is_empty() {
+        println!("✅ No fixes have been recorded yet.");
+        return;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    for entry in journal.entries.iter().rev() {
+        println!(
+            "{} {} by {}{}",
+            entry.id.bright_cyan(),
+            ago(now.saturating_sub(entry.timestamp)).bright_black(),
+            entry.model,
+            if entry.undone {
+                " (undone)".bright_black().to_string()
+            } else {
+                String::new()
+            }
+        );
+        println!("  {}", entry.headline());
+        println!("  {}", files(entry));
+    }
+}
+
+fn files(entry: &Entry) -> String {
+    entry
+        .files
+        .iter()
+        .map(|fix| fix.file.bright_yellow().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// How long ago something happened, in the largest unit that fits
+fn ago(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
@@ -1,247 +1,220 @@
 use colored::Colorize;
-use miette::Result;
-use openai::chat::ChatCompletionMessage;
-use serde::Deserialize;
+use miette::{IntoDiagnostic, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
 use std::{
-    io::{BufRead, BufReader},
-    process::{Command, Stdio},
+    collections::HashSet,
+    path::{Path, PathBuf},
     time::Duration,
 };
+use tokio::sync::mpsc;
+
+use crate::{
+    config::Config,
+    diagnostics::{
+        check::{check_with_spinner, spawn_check},
+        diagnostic::Diagnostic,
+    },
+    fixer::repair::fix_errors,
+    patch::git,
+    providers,
+    workspace::{display, Workspace},
+};
+
+// Directories that are watched recursively for changes
+const WATCHED_DIRECTORIES: [&str; 3] = ["src", "tests", "benches"];
+
+// Files that are watched on their own
+const WATCHED_FILES: [&str; 1] = ["Cargo.toml"];
 
-use crate::config::Config;
+// How long the filesystem has to stay quiet before `cargo check` is re-run
+const DEBOUNCE: Duration = Duration::from_millis(500);
 
-#[derive(PartialEq, Debug, Clone)]
-pub struct Error {
-    pub sha: String,
-    pub file: String,
-    pub message: String,
+// The directories of the packages that are checked: the selected ones, or every workspace member
+fn package_roots(config: &Config, workspace: &Workspace) -> Vec<PathBuf> {
+    workspace
+        .members
+        .iter()
+        .filter(|package| config.packages.is_empty() || config.packages.contains(&package.name))
+        .map(|package| package.root.clone())
+        .collect()
 }
 
-#[derive(Deserialize)]
-pub struct Change {
-    pub file: String,
-    pub line_number: usize,
-    pub new_line: String,
-    pub time_estimate_seconds: u64,
+// Whether a changed path should trigger a new `cargo check`
+fn is_watched(path: &Path, roots: &[PathBuf], workspace: &Workspace) -> bool {
+    // The workspace manifest may not belong to any package
+    if path == workspace.root.join("Cargo.toml") {
+        return true;
+    }
+
+    roots.iter().any(|root| {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+
+        if WATCHED_FILES.iter().any(|file| relative == Path::new(file)) {
+            return true;
+        }
+
+        // Only Rust sources matter inside the watched directories, this skips editor swap files
+        WATCHED_DIRECTORIES
+            .iter()
+            .any(|directory| relative.starts_with(directory))
+            && relative
+                .extension()
+                .is_some_and(|extension| extension == "rs")
+    })
 }
 
-#[derive(Deserialize)]
-pub struct Changes {
-    pub changes: Vec<Change>,
+// Whether a filesystem event represents a save we should react to
+fn is_relevant(event: &Event, roots: &[PathBuf], workspace: &Workspace) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) && event
+        .paths
+        .iter()
+        .any(|path| is_watched(path, roots, workspace))
 }
 
-pub fn spawn_check() -> Vec<Error> {
-    let mut errors = Vec::new();
+pub async fn execute(auto_apply: bool, packages: Vec<String>, no_cache: bool) -> Result<()> {
+    println!("⭐ Neura has joined your session.");
 
-    let mut child = Command::new("cargo")
-        .arg("check")
-        .arg("--message-format=json")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .stdin(Stdio::piped())
-        .spawn()
-        .expect("Failed to start cargo check");
+    let mut config = Config::load();
 
-    if let Some(ref mut stdout) = child.stdout {
-        let reader = BufReader::new(stdout);
+    // Flags passed in take priority over `neura.toml`
+    config.auto_apply |= auto_apply;
 
-        for line in reader.lines() {
-            let line = line.expect("Failed to read line");
+    if !packages.is_empty() {
+        config.packages = packages;
+    }
 
-            // Parse the JSON output and process it
-            let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    if no_cache {
+        config.cache.enabled = false;
+    }
 
-            // Check if the JSON output is a compiler message
-            if json["reason"] == "compiler-message" {
-                // Check if the level is an error
-                if json["message"]["level"] != "error" {
-                    continue;
-                }
+    config.validate_packages(Workspace::current())?;
 
-                let rendered_error = json["message"]["rendered"].as_str().unwrap();
+    // Before anything is edited
+    git::prepare(&config.git)?;
 
-                // Check if spans exist
-                let spans = json["message"]["spans"].as_array().unwrap();
+    let provider = providers::from_config(&config)?;
 
-                if spans.len() == 0 {
-                    continue;
-                }
+    let check = || spawn_check(&config);
 
-                let file_name = json["message"]["spans"][0]["file_name"]
-                    .as_str()
-                    .unwrap()
-                    .to_string();
+    let initial_errors = check_with_spinner(&config);
+    let remaining_errors = fix_errors(
+        &config,
+        provider.as_ref(),
+        &initial_errors,
+        &initial_errors,
+        &check,
+    )
+    .await;
 
-                errors.push(Error {
-                    sha: sha256::digest(format!("{}:{}", file_name, rendered_error)),
-                    file: file_name,
-                    message: rendered_error.to_string(),
-                });
-            }
-        }
-    }
+    // Errors that have already been seen, identified by their sha
+    // These are not sent to the model again until they disappear and come back
+    let mut known_errors: HashSet<String> =
+        remaining_errors.iter().map(|error| error.sha()).collect();
 
-    child.wait().expect("Failed to wait on child");
+    // The watcher callback runs on its own thread, so events are forwarded over a channel
+    let (sender, mut receiver) = mpsc::unbounded_channel::<notify::Result<Event>>();
 
-    errors
-}
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })
+    .into_diagnostic()?;
 
-pub async fn execute() -> Result<()> {
-    println!("⭐ Neura has joined your session.");
-
-    let config = Config::load();
-
-    let spinner = indicatif::ProgressBar::new_spinner();
-    spinner.set_message("💻 Running `cargo check` ...");
-    spinner.enable_steady_tick(Duration::from_millis(100));
-    let initial_errors = spawn_check();
-    spinner.finish_and_clear();
-
-    for error in initial_errors.iter() {
-        // Read the contents of error.file
-        let contents = std::fs::read_to_string(&error.file).unwrap();
-
-        openai::set_key(std::env::var("NEURA_API_KEY").unwrap());
-
-        // Start spinner
-        let spinner = indicatif::ProgressBar::new_spinner();
-        spinner.set_message("🐛 Debugging your issue ...");
-        spinner.enable_steady_tick(Duration::from_millis(100));
-
-        let prompt = format!(
-            "You are an AI debugging copilot: fix this Rust error:\nCargo Error: {}\nFile Contents: {}\n\nRespond with a JSON. Use 'changes' for changes needed. Each change should have 'file' (filename), 'line_number' (line to be changed), 'new_line' (new line content), and 'time_estimate_seconds' (time to resolve manually). E.g.: {{\"changes\": [{{\"file\": \"src/main.rs\", \"line_number\": 3, \"new_line\": \"pub fn main() {{}}\", \"time_estimate_seconds\": 20}}]}}",
-            error.message, contents
-        );
-
-        let bpe = tiktoken_rs::cl100k_base().unwrap();
-        let prompt_tokens = bpe.encode_with_special_tokens(&prompt);
-
-        // Prompt token count
-        let prompt_token_count = prompt_tokens.len();
-        println!("📝 Prompt token count: {}", prompt_token_count);
-
-        let model = config.model.unwrap();
-
-        let completion = openai::chat::ChatCompletion::builder(
-            &model.code(),
-            vec![ChatCompletionMessage {
-                role: openai::chat::ChatCompletionMessageRole::User,
-                content: prompt,
-                name: None,
-            }],
-        )
-        .max_tokens(300 as u64)
-        .temperature(0.2)
-        .create()
-        .await
-        .unwrap()
-        .unwrap();
-
-        // Stop spinner
-        spinner.finish_and_clear();
-
-        let returned_message = completion.choices.first().unwrap().message.clone();
-
-        // println!(
-        //     "🤖 The AI has responded with the following message: {}",
-        //     returned_message.content
-        // );
-
-        let response_tokens = bpe.encode_with_special_tokens(&returned_message.content);
-
-        // Prompt token count
-        let response_token_count = response_tokens.len();
-
-        // Convert the output into a json object
-        let changes: Changes = serde_json::from_str(&returned_message.content).unwrap();
-
-        let mut estimated_time = 0;
-
-        for change in changes.changes {
-            // Read the file again
-            let mut contents = std::fs::read_to_string(&change.file).unwrap();
-
-            // Apply the change
-            let line_number = change.line_number;
-
-            // Split the contents into lines
-            let mut lines = contents.lines().collect::<Vec<&str>>();
-
-            // Check if the line number is valid
-            if line_number <= lines.len() + 1 {
-                // Replace the line if it exists or add a new line
-                println!(
-                    "{} Editing {}, line {}",
-                    ">".bright_black(),
-                    change.file.bright_yellow(),
-                    line_number
-                );
-                if line_number <= lines.len() {
-                    lines[line_number - 1] = change.new_line.as_str();
-                } else {
-                    lines.push(change.new_line.as_str());
-                }
+    let workspace = Workspace::current();
+    let roots = package_roots(&config, workspace);
 
-                // Join the lines back together
-                contents = lines.join("\n");
+    let mut watched_paths: Vec<PathBuf> = Vec::new();
 
-                // Write the new contents to the file
-                std::fs::write(&change.file, &contents).unwrap();
+    for root in &roots {
+        for directory in WATCHED_DIRECTORIES {
+            let directory = root.join(directory);
 
-                estimated_time += change.time_estimate_seconds;
-            } else {
-                println!("Error: Invalid line number {}", line_number);
+            if directory.is_dir() {
+                watcher
+                    .watch(&directory, RecursiveMode::Recursive)
+                    .into_diagnostic()?;
+                watched_paths.push(directory);
             }
         }
 
-        // Verify that the fixes applied have resolved the error
-        let errors = spawn_check();
+        for file in WATCHED_FILES {
+            let file = root.join(file);
 
-        let mut fixed_errors = 0;
-
-        for old_error in &initial_errors {
-            let still_exists = errors
-                .iter()
-                .any(|current_error| current_error.message == old_error.message);
-
-            if !still_exists {
-                fixed_errors += 1;
+            if file.is_file() {
+                watcher
+                    .watch(&file, RecursiveMode::NonRecursive)
+                    .into_diagnostic()?;
+                watched_paths.push(file);
             }
         }
+    }
 
-        // Assume a 50$/hr rate
-        let rate_per_second: f64 = 50.0 / 3600.0;
-        let cost_savings = estimated_time as f64 * rate_per_second;
+    let manifest = workspace.root.join("Cargo.toml");
 
-        let mut prompt_cost: f64 = 0.0;
-        let mut response_cost: f64 = 0.0;
+    if !watched_paths.contains(&manifest) && manifest.is_file() {
+        watcher
+            .watch(&manifest, RecursiveMode::NonRecursive)
+            .into_diagnostic()?;
+        watched_paths.push(manifest);
+    }
 
-        // Calcuate how much it cost to run the AI
-        match model {
-            crate::models::model::Model::GPT4 => {
-                prompt_cost = (prompt_token_count as f64 / 1000.0) * 0.06;
-                response_cost = (response_token_count as f64 / 1000.0) * 0.12;
+    println!(
+        "👀 Watching {} for changes ...",
+        watched_paths
+            .iter()
+            .map(|path| display(path).bright_cyan().to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    while let Some(event) = receiver.recv().await {
+        match event {
+            Ok(event) if is_relevant(&event, &roots, workspace) => {}
+            Ok(_) => continue,
+            Err(err) => {
+                println!("{} Watch error: {}", ">".bright_black(), err);
+                continue;
             }
-            crate::models::model::Model::GPT3Turbo => {
-                prompt_cost = (prompt_token_count as f64 / 1000.0) * 0.002;
-                response_cost = (response_token_count as f64 / 1000.0) * 0.002;
-            }
-            crate::models::model::Model::ClaudeV1 => {}
         }
 
-        let total_cost = prompt_cost + response_cost;
-
-        println!(
-            "✅ Successfully resolved {} {}, saving you {}. {} remain.",
-            fixed_errors.to_string().bright_green(),
-            if fixed_errors == 1 { "error" } else { "errors" },
-            format!("{:.2}$", (cost_savings - total_cost)).bright_cyan(),
-            if errors.len() >= 1 {
-                errors.len().to_string().bright_red()
-            } else {
-                "0".bright_green()
-            }
-        );
+        // Editors often emit several events per save, so wait until things settle down
+        while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {}
+
+        let errors = check_with_spinner(&config);
+
+        let new_errors = errors
+            .iter()
+            .filter(|error| !known_errors.contains(&error.sha()))
+            .cloned()
+            .collect::<Vec<Diagnostic>>();
+
+        let errors = if new_errors.is_empty() {
+            errors
+        } else {
+            println!(
+                "🔎 Found {} new {}.",
+                new_errors.len().to_string().bright_red(),
+                if new_errors.len() == 1 {
+                    "error"
+                } else {
+                    "errors"
+                }
+            );
+
+            let remaining_errors =
+                fix_errors(&config, provider.as_ref(), &errors, &new_errors, &check).await;
+
+            // Our own edits trigger events as well, these have already been checked
+            while receiver.try_recv().is_ok() {}
+
+            remaining_errors
+        };
+
+        known_errors = errors.iter().map(|error| error.sha()).collect();
     }
 
     Ok(())
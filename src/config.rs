@@ -1,6 +1,14 @@
-use std::io::Write;
+use std::{collections::BTreeMap, io::Write};
 
-use crate::models::model::Model;
+use crate::{
+    models::{
+        model::Model,
+        pricing::{list_price, Price},
+    },
+    patch::{git::DirtyPolicy, rollback::RollbackPolicy},
+    providers::provider::ProviderKind,
+    workspace::Workspace,
+};
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
@@ -25,18 +33,307 @@ where
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(
         serialize_with = "serialize_model",
         deserialize_with = "deserialize_model"
     )]
     pub model: Option<Model>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderConfig>,
+
+    /// Write fixes without reviewing them first
+    #[serde(default)]
+    pub auto_apply: bool,
+
+    /// When fixes that make the build worse are reverted
+    #[serde(default)]
+    pub rollback: RollbackPolicy,
+
+    #[serde(default)]
+    pub repair: RepairConfig,
+
+    #[serde(default)]
+    pub context: ContextConfig,
+
+    #[serde(default)]
+    pub lints: LintConfig,
+
+    #[serde(default)]
+    pub git: GitConfig,
+
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    /// Prices of models by id, in dollars per 1K tokens, taking priority over the built-in ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: BTreeMap<String, Price>,
+
+    /// Workspace members to check, test or run, like `cargo -p`, every member when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<String>,
+}
+
+/// The `[provider]` section of `neura.toml`, every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// Backend to send requests to, inferred from the model when omitted
+    pub kind: Option<ProviderKind>,
+
+    /// Base URL of the API, e.g. `http://localhost:11434/v1` for Ollama
+    pub base_url: Option<String>,
+
+    /// Environment variable holding the API key, defaults to `NEURA_API_KEY`
+    pub api_key_env: Option<String>,
+
+    /// Model name sent to the backend, overriding the code of the selected model
+    pub model: Option<String>,
+
+    /// Whether fixes are requested through function calling / tool use instead of instructions in the prompt.
+    /// Defaults to what the selected model supports, and to off for local servers.
+    pub tools: Option<bool>,
+}
+
+/// The `[repair]` section of `neura.toml`, limits how hard neura tries to fix a single error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairConfig {
+    /// Maximum number of requests sent to the model for a single error
+    #[serde(default = "RepairConfig::default_max_attempts")]
+    pub max_attempts: usize,
+
+    /// Stop retrying once the requests for a single error have cost this much, in dollars
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+
+    /// Apply rustc's machine-applicable suggestions before asking the model
+    #[serde(default = "RepairConfig::default_compiler_suggestions")]
+    pub compiler_suggestions: bool,
+
+    /// Ask the model once to fix a response that couldn't be parsed, before counting the attempt as failed
+    #[serde(default = "RepairConfig::default_repair_responses")]
+    pub repair_responses: bool,
+
+    /// How many errors in different files are sent to the model at the same time
+    #[serde(default = "RepairConfig::default_concurrency")]
+    pub concurrency: usize,
+
+    /// Send related errors in the same file, e.g. every use of a misspelled name, in a single request
+    #[serde(default = "RepairConfig::default_cluster")]
+    pub cluster: bool,
+}
+
+impl RepairConfig {
+    fn default_max_attempts() -> usize {
+        3
+    }
+
+    fn default_compiler_suggestions() -> bool {
+        true
+    }
+
+    fn default_repair_responses() -> bool {
+        true
+    }
+
+    fn default_concurrency() -> usize {
+        4
+    }
+
+    fn default_cluster() -> bool {
+        true
+    }
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: Self::default_max_attempts(),
+            max_cost: None,
+            compiler_suggestions: Self::default_compiler_suggestions(),
+            repair_responses: Self::default_repair_responses(),
+            concurrency: Self::default_concurrency(),
+            cluster: Self::default_cluster(),
+        }
+    }
+}
+
+/// The `[context]` section of `neura.toml`, controls how much source code is sent with an error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Maximum number of tokens of source code sent with an error
+    #[serde(default = "ContextConfig::default_max_tokens")]
+    pub max_tokens: usize,
+
+    /// Number of lines shown above and below the error
+    #[serde(default = "ContextConfig::default_window")]
+    pub window: usize,
+}
+
+impl ContextConfig {
+    fn default_max_tokens() -> usize {
+        2000
+    }
+
+    fn default_window() -> usize {
+        20
+    }
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: Self::default_max_tokens(),
+            window: Self::default_window(),
+        }
+    }
+}
+
+/// The `[lints]` section of `neura.toml`, which diagnostics besides errors get fixed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LintConfig {
+    /// Fix rustc warnings too
+    #[serde(default)]
+    pub warnings: bool,
+
+    /// Run `cargo clippy` instead of `cargo check` and fix its lints too
+    #[serde(default)]
+    pub clippy: bool,
+
+    /// Lints that are never fixed, e.g. `dead_code` or `clippy::too_many_arguments`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    /// Lints that are always fixed, even when their kind isn't turned on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+impl LintConfig {
+    /// Whether clippy has to run to report the lints that should be fixed
+    pub fn needs_clippy(&self) -> bool {
+        self.clippy || self.deny.iter().any(|lint| lint.starts_with("clippy::"))
+    }
+
+    /// Whether a warning with the given lint name should be fixed
+    pub fn should_fix(&self, lint: &str) -> bool {
+        if self.allow.iter().any(|allowed| allowed == lint) {
+            return false;
+        }
+
+        if self.deny.iter().any(|denied| denied == lint) {
+            return true;
+        }
+
+        if lint.starts_with("clippy::") {
+            self.clippy
+        } else {
+            self.warnings
+        }
+    }
+}
+
+/// The `[git]` section of `neura.toml`, how fixes are recorded in git
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitConfig {
+    /// Turn git integration on, everything below is ignored otherwise
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// What to do when tracked files have uncommitted changes before anything is edited
+    #[serde(default)]
+    pub dirty: DirtyPolicy,
+
+    /// Make the fixes on a `neura/fix-<sha>` branch, named after the commit they start from
+    #[serde(default)]
+    pub branch: bool,
+
+    /// Commit every accepted fix on its own, with the diagnostic it fixes in the message
+    #[serde(default = "GitConfig::default_commit")]
+    pub commit: bool,
+}
+
+impl GitConfig {
+    fn default_commit() -> bool {
+        true
+    }
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dirty: DirtyPolicy::default(),
+            branch: false,
+            commit: Self::default_commit(),
+        }
+    }
+}
+
+/// The `[cache]` section of `neura.toml`, reuses the model's answers to prompts it has seen before
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Look for an answer in `.neura/cache` before asking the model
+    #[serde(default = "CacheConfig::default_enabled")]
+    pub enabled: bool,
+
+    /// How long an answer is reused, in hours
+    #[serde(default = "CacheConfig::default_ttl_hours")]
+    pub ttl_hours: u64,
+
+    /// How large the cache may grow, in megabytes, the oldest answers are dropped first
+    #[serde(default = "CacheConfig::default_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+impl CacheConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_ttl_hours() -> u64 {
+        7 * 24
+    }
+
+    fn default_max_size_mb() -> u64 {
+        50
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            ttl_hours: Self::default_ttl_hours(),
+            max_size_mb: Self::default_max_size_mb(),
+        }
+    }
+}
+
+/// The `[budget]` section of `neura.toml`, caps on what is spent on the model, in dollars.
+/// A request that would go over one is only sent after asking.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Most a single request may cost
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_request: Option<f64>,
+
+    /// Most a single run of neura may cost, e.g. a whole watch session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session: Option<f64>,
+
+    /// Most that may be spent in a day, across sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_day: Option<f64>,
 }
 
 impl Config {
     pub fn new() -> Self {
-        Self { model: None }
+        Self::default()
     }
 
     pub fn set_model(&mut self, model: Model) {
@@ -55,6 +352,65 @@ impl Config {
         toml::from_str(&toml).unwrap()
     }
 
+    /// The name of the model as the backend knows it, `[provider] model` when that is set
+    pub fn model_name(&self) -> String {
+        self.provider
+            .as_ref()
+            .and_then(|provider| provider.model.clone())
+            .or_else(|| self.model.map(|model| model.code()))
+            .unwrap_or_default()
+    }
+
+    /// What the model costs: the price in `[pricing]`, otherwise its list price.
+    /// Local servers and models without a known price are free.
+    pub fn price(&self) -> Price {
+        let model = self.model_name();
+
+        if let Some(price) = self.pricing.get(&model) {
+            return *price;
+        }
+
+        let kind = self
+            .provider
+            .as_ref()
+            .and_then(|provider| provider.kind)
+            .or_else(|| self.model.map(|model| model.provider()));
+
+        if kind == Some(ProviderKind::Local) {
+            return Price::default();
+        }
+
+        list_price(&model).unwrap_or_default()
+    }
+
+    /// `-p` arguments selecting `packages` for a cargo command
+    pub fn package_args(&self) -> Vec<String> {
+        self.packages
+            .iter()
+            .flat_map(|package| [String::from("-p"), package.clone()])
+            .collect()
+    }
+
+    /// Fails when a selected package isn't a member of the workspace
+    pub fn validate_packages(&self, workspace: &Workspace) -> miette::Result<()> {
+        for package in &self.packages {
+            if workspace.package(package).is_none() {
+                return Err(miette::miette!(
+                    "`{}` is not a member of the workspace, expected one of: {}",
+                    package,
+                    workspace
+                        .members
+                        .iter()
+                        .map(|member| member.name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn is_initialized() -> bool {
         std::path::Path::new("neura.toml").exists()
     }
@@ -1,4 +1,12 @@
 use once_cell::sync::Lazy;
 
-pub static COMMANDS_LIST: Lazy<Vec<String>> =
-    Lazy::new(|| vec![String::from("init"), String::from("watch")]);
+pub static COMMANDS_LIST: Lazy<Vec<String>> = Lazy::new(|| {
+    vec![
+        String::from("explain"),
+        String::from("init"),
+        String::from("run"),
+        String::from("test"),
+        String::from("undo"),
+        String::from("watch"),
+    ]
+});
@@ -0,0 +1,2 @@
+/// Where neura keeps what it remembers between sessions, next to `neura.toml`
+pub const DATA_DIRECTORY: &str = ".neura";
@@ -0,0 +1,135 @@
+use serde::Deserialize;
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use crate::{config::Config, workspace::Workspace};
+
+use super::diagnostic::{Diagnostic, Level};
+
+/// A line of cargo's `--message-format=json` output
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+/// Runs `cargo check`, or `cargo clippy` when lints need it, on the selected packages and returns
+/// the diagnostics neura should fix: every error, plus the warnings and lints turned on in `[lints]`.
+pub fn spawn_check(config: &Config) -> Vec<Diagnostic> {
+    spawn_cargo(
+        &[if config.lints.needs_clippy() {
+            "clippy"
+        } else {
+            "check"
+        }],
+        config,
+    )
+}
+
+/// Runs a cargo command that builds the crate, e.g. `cargo test --no-run`, and returns the diagnostics
+/// neura should fix, the same way `spawn_check` does.
+///
+/// Paths in the diagnostics are resolved against the workspace root, so they can be opened from the current directory.
+pub fn spawn_cargo(args: &[&str], config: &Config) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    let workspace = Workspace::current();
+
+    let mut child = Command::new("cargo")
+        .args(args)
+        .args(config.package_args())
+        .arg("--message-format=json")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Failed to start cargo");
+
+    if let Some(ref mut stdout) = child.stdout {
+        let reader = BufReader::new(stdout);
+
+        for line in reader.lines() {
+            let line = line.expect("Failed to read line");
+
+            // Parse the JSON output, build scripts may print lines that aren't JSON
+            let Ok(json) = serde_json::from_str::<CargoMessage>(&line) else {
+                continue;
+            };
+
+            // Check if the JSON output is a compiler message
+            if json.reason != "compiler-message" {
+                continue;
+            }
+
+            let Some(mut diagnostic) = json.message else {
+                continue;
+            };
+
+            // Warnings are only kept when their lint is turned on
+            let wanted = match diagnostic.level {
+                Level::Error => true,
+                Level::Warning => diagnostic
+                    .code
+                    .as_ref()
+                    .is_some_and(|code| config.lints.should_fix(&code.code)),
+                _ => false,
+            };
+
+            if !wanted {
+                continue;
+            }
+
+            // Errors without spans (e.g. "aborting due to previous error") can't be fixed in a file
+            if diagnostic.spans.is_empty() {
+                continue;
+            }
+
+            workspace.resolve_spans(&mut diagnostic);
+            errors.push(diagnostic);
+        }
+    }
+
+    child.wait().expect("Failed to wait on child");
+
+    errors
+}
+
+/// Runs `cargo check` behind a spinner
+pub fn check_with_spinner(config: &Config) -> Vec<Diagnostic> {
+    let spinner = indicatif::ProgressBar::new_spinner();
+    spinner.set_message(if config.lints.needs_clippy() {
+        "💻 Running `cargo clippy` ..."
+    } else {
+        "💻 Running `cargo check` ..."
+    });
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let errors = spawn_check(config);
+    spinner.finish_and_clear();
+
+    errors
+}
+
+/// Errors in `after` that weren't in `before`
+pub fn introduced_errors(before: &[Diagnostic], after: &[Diagnostic]) -> Vec<Diagnostic> {
+    let mut remaining = before.iter().collect::<Vec<&Diagnostic>>();
+
+    after
+        .iter()
+        .filter(|error| {
+            // Each error from before can only account for one error after
+            match remaining
+                .iter()
+                .position(|existing| existing.is_same(error))
+            {
+                Some(index) => {
+                    remaining.swap_remove(index);
+                    false
+                }
+                None => true,
+            }
+        })
+        .cloned()
+        .collect()
+}
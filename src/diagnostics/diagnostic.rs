@@ -0,0 +1,233 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Severity of a diagnostic, as reported by rustc
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Level {
+    #[serde(rename = "error")]
+    Error,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "note")]
+    Note,
+    #[serde(rename = "help")]
+    Help,
+    #[serde(rename = "failure-note")]
+    FailureNote,
+    #[serde(rename = "error: internal compiler error")]
+    InternalCompilerError,
+    #[serde(other)]
+    Other,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+            Self::Help => write!(f, "help"),
+            Self::FailureNote => write!(f, "failure-note"),
+            Self::InternalCompilerError => write!(f, "error: internal compiler error"),
+            Self::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// How confident rustc is that a suggested replacement is correct
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified,
+}
+
+/// An error code such as `E0425`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Code {
+    pub code: String,
+    pub explanation: Option<String>,
+}
+
+/// A line of source covered by a span
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpanLine {
+    pub text: String,
+
+    /// 1-based column where the highlight starts
+    pub highlight_start: usize,
+
+    /// 1-based column where the highlight ends, exclusive
+    pub highlight_end: usize,
+}
+
+impl SpanLine {
+    /// The highlighted part of the line
+    pub fn highlighted(&self) -> String {
+        let start = self.highlight_start.saturating_sub(1);
+        let end = self.highlight_end.saturating_sub(1);
+
+        self.text
+            .chars()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+}
+
+/// Where a span came from when it was produced by a macro
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expansion {
+    pub span: Span,
+    pub macro_decl_name: String,
+    pub def_site_span: Option<Span>,
+}
+
+/// A region of source code a diagnostic points at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub file_name: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+
+    /// 1-based, inclusive
+    pub line_start: usize,
+    pub line_end: usize,
+
+    /// 1-based, `column_end` is exclusive
+    pub column_start: usize,
+    pub column_end: usize,
+
+    /// Whether this is the main location of the diagnostic rather than supporting context
+    pub is_primary: bool,
+
+    #[serde(default)]
+    pub text: Vec<SpanLine>,
+
+    pub label: Option<String>,
+    pub suggested_replacement: Option<String>,
+    pub suggestion_applicability: Option<Applicability>,
+    pub expansion: Option<Box<Expansion>>,
+}
+
+impl Span {
+    /// The outermost span this one was expanded from, or itself if it isn't part of a macro expansion
+    ///
+    /// This is where the user's own code is, e.g. the `println!` call rather than the inside of the macro.
+    pub fn invocation(&self) -> &Span {
+        match &self.expansion {
+            Some(expansion) => expansion.span.invocation(),
+            None => self,
+        }
+    }
+}
+
+/// A compiler diagnostic, deserialized from rustc's JSON output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub code: Option<Code>,
+    pub level: Level,
+
+    #[serde(default)]
+    pub spans: Vec<Span>,
+
+    /// Notes and help messages attached to this diagnostic
+    #[serde(default)]
+    pub children: Vec<Diagnostic>,
+
+    /// The diagnostic as rustc would print it
+    pub rendered: Option<String>,
+}
+
+impl Diagnostic {
+    /// The span the diagnostic is about, falling back to the first span if none are marked primary
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.spans
+            .iter()
+            .find(|span| span.is_primary)
+            .or_else(|| self.spans.first())
+    }
+
+    /// The file the diagnostic is reported in
+    pub fn file(&self) -> Option<&str> {
+        self.primary_span().map(|span| span.file_name.as_str())
+    }
+
+    /// The 1-based line the diagnostic is reported on
+    pub fn line(&self) -> Option<usize> {
+        self.primary_span().map(|span| span.line_start)
+    }
+
+    /// The first line of the rendered diagnostic, e.g. "error[E0425]: cannot find value `x` in this scope"
+    ///
+    /// Unlike the rendered message this doesn't contain line numbers, so it survives edits elsewhere in the file.
+    pub fn headline(&self) -> String {
+        match &self.code {
+            Some(code) => format!("{}[{}]: {}", self.level, code.code, self.message),
+            None => format!("{}: {}", self.level, self.message),
+        }
+    }
+
+    /// The diagnostic as rustc would print it, or as close as we can get when it wasn't rendered
+    pub fn rendered(&self) -> String {
+        match &self.rendered {
+            Some(rendered) => rendered.clone(),
+            None => {
+                let mut rendered = self.headline();
+
+                if let Some(span) = self.primary_span() {
+                    rendered.push_str(&format!(
+                        "\n  --> {}:{}:{}",
+                        span.file_name, span.line_start, span.column_start
+                    ));
+                }
+
+                for child in &self.children {
+                    rendered.push_str(&format!("\n  = {}", child.headline()));
+                }
+
+                rendered
+            }
+        }
+    }
+
+    /// Identifies the diagnostic across runs.
+    ///
+    /// It is built from the file, code, message and highlighted source rather than line numbers,
+    /// so the same problem keeps its sha when unrelated lines are added or removed.
+    pub fn sha(&self) -> String {
+        let highlighted = self
+            .primary_span()
+            .map(|span| {
+                span.text
+                    .iter()
+                    .map(|line| line.highlighted())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        sha256::digest(format!(
+            "{}:{}:{}",
+            self.file().unwrap_or_default(),
+            self.headline(),
+            highlighted
+        ))
+    }
+
+    /// Whether both diagnostics are the same problem, even if it moved around in the file
+    pub fn is_same(&self, other: &Diagnostic) -> bool {
+        self.file() == other.file() && self.headline() == other.headline()
+    }
+
+    /// Every span of this diagnostic and its children
+    pub fn all_spans(&self) -> Vec<&Span> {
+        self.spans
+            .iter()
+            .chain(self.children.iter().flat_map(|child| child.all_spans()))
+            .collect()
+    }
+}
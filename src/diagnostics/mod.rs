@@ -0,0 +1,5 @@
+pub mod check;
+pub mod diagnostic;
+pub mod panic;
+pub mod run;
+pub mod test;
@@ -0,0 +1,463 @@
+use std::path::Path;
+
+use similar::{ChangeTag, TextDiff};
+
+use crate::workspace::Workspace;
+
+use super::diagnostic::{Code, Diagnostic, Level, Span};
+
+/// A position in a source file, as printed in panic messages and backtraces
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+
+    /// 1-based
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    // Parses `src/lib.rs:10:5`, the path itself may contain colons
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().rsplitn(3, ':');
+
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?.to_string();
+
+        Some(Self { file, line, column })
+    }
+
+    /// The path as it can be opened from the current directory, or `None` when the file isn't part of
+    /// a workspace member, like the standard library or dependencies in the cargo registry
+    pub fn workspace_file(&self) -> Option<String> {
+        let workspace = Workspace::current();
+
+        // Panic locations and backtraces are relative to the workspace root, where rustc ran
+        let file = workspace.resolve(&self.file);
+
+        (workspace.is_editable(&file) && Path::new(&file).is_file()).then_some(file)
+    }
+}
+
+/// A frame of a `RUST_BACKTRACE=1` backtrace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub location: Option<Location>,
+}
+
+/// A panic, parsed from the output of a test or program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panic {
+    pub thread: String,
+    pub location: Option<Location>,
+
+    /// Everything the panic printed, including assertion values
+    pub message: String,
+
+    pub backtrace: Vec<Frame>,
+}
+
+impl Panic {
+    /// Finds the first panic in `output`.
+    ///
+    /// Both the current format (`panicked at src/lib.rs:1:5:` followed by the message)
+    /// and the one before Rust 1.73 (`panicked at 'message', src/lib.rs:1:5`) are understood.
+    pub fn parse(output: &str) -> Option<Self> {
+        let lines: Vec<&str> = output.lines().collect();
+
+        let start = lines
+            .iter()
+            .position(|line| line.starts_with("thread '") && line.contains(" panicked at "))?;
+
+        // Newer toolchains print the thread id after its name: `thread 'main' (1234) panicked at`
+        let (thread, rest) = lines[start]
+            .strip_prefix("thread '")?
+            .split_once(" panicked at ")?;
+        let thread = thread.rsplit_once('\'')?.0;
+
+        let mut message_lines: Vec<&str> = Vec::new();
+        let mut index = start + 1;
+
+        let location = if let Some(old) = rest.strip_prefix('\'') {
+            // Before 1.73: the message is quoted and the location comes after it,
+            // on the last line of the message when it spans several, like assertions do
+            let mut line = old;
+
+            loop {
+                if let Some((message, location)) = line
+                    .rsplit_once("', ")
+                    .and_then(|(message, location)| Some((message, Location::parse(location)?)))
+                {
+                    message_lines.push(message);
+                    break Some(location);
+                }
+
+                message_lines.push(line);
+                line = lines.get(index)?;
+                index += 1;
+            }
+        } else {
+            Location::parse(rest.trim_end_matches(':'))
+        };
+
+        while index < lines.len() {
+            let line = lines[index];
+
+            if line.starts_with("note: ") || line.starts_with("stack backtrace:") {
+                break;
+            }
+
+            message_lines.push(line);
+            index += 1;
+        }
+
+        let backtrace = parse_backtrace(&lines[index..]);
+
+        Some(Self {
+            thread: thread.to_string(),
+            location,
+            message: message_lines.join("\n").trim().to_string(),
+            backtrace,
+        })
+    }
+
+    /// The frames that are in the crate's own code, innermost first
+    pub fn workspace_frames(&self) -> Vec<(&Frame, String)> {
+        self.backtrace
+            .iter()
+            .filter_map(|frame| {
+                let file = frame.location.as_ref()?.workspace_file()?;
+                Some((frame, file))
+            })
+            .collect()
+    }
+
+    /// A line diff of the `left` and `right` values of a failed `assert_eq!` or `assert_ne!`
+    pub fn assertion_diff(&self) -> Option<String> {
+        let value = |prefix: &str| {
+            self.message
+                .lines()
+                .find_map(|line| line.trim_start().strip_prefix(prefix))
+                .map(|value| value.trim().to_string())
+        };
+
+        let left = value("left:")?;
+        let right = value("right:")?;
+
+        // Pretty-printed values are easier to compare line by line
+        let (left, right) = (left.replace(", ", ",\n"), right.replace(", ", ",\n"));
+
+        let diff = TextDiff::from_lines(&left, &right);
+
+        Some(
+            diff.iter_all_changes()
+                .map(|change| {
+                    let sign = match change.tag() {
+                        ChangeTag::Delete => "-",
+                        ChangeTag::Insert => "+",
+                        ChangeTag::Equal => " ",
+                    };
+
+                    format!("{}{}", sign, change.value().trim_end())
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+        )
+    }
+
+    /// Describes the panic for the model: the message, the assertion diff and the crate's own frames
+    pub fn render(&self) -> String {
+        let mut rendered = match &self.location {
+            Some(location) => format!(
+                "thread '{}' panicked at {}:{}:{}:\n{}",
+                self.thread, location.file, location.line, location.column, self.message
+            ),
+            None => format!("thread '{}' panicked:\n{}", self.thread, self.message),
+        };
+
+        if let Some(diff) = self.assertion_diff() {
+            rendered.push_str(&format!("\nAssertion diff (- left, + right):\n{}", diff));
+        }
+
+        let frames = self.workspace_frames();
+
+        if !frames.is_empty() {
+            rendered.push_str("\nBacktrace (crate frames, innermost first):");
+
+            for (frame, file) in frames {
+                let location = frame.location.as_ref().unwrap();
+
+                rendered.push_str(&format!(
+                    "\n  {} at {}:{}:{}",
+                    frame.function, file, location.line, location.column
+                ));
+            }
+        }
+
+        rendered
+    }
+
+    /// Turns the panic into a diagnostic, so it can be fixed like a compiler error.
+    ///
+    /// `message` identifies it across runs, `anchor` is where the code that failed lives.
+    /// The panic location and the crate frames of the backtrace are added as related spans.
+    pub fn to_diagnostic(&self, message: String, anchor: Option<Location>) -> Diagnostic {
+        let mut spans: Vec<Span> = Vec::new();
+
+        if let Some(anchor) = anchor
+            .as_ref()
+            .and_then(|anchor| Some(span(&anchor.workspace_file()?, anchor, None, true)))
+        {
+            spans.push(anchor);
+        }
+
+        if let Some(location) = &self.location {
+            if let Some(file) = location.workspace_file() {
+                spans.push(span(
+                    &file,
+                    location,
+                    Some(String::from("panicked here")),
+                    spans.is_empty(),
+                ));
+            }
+        }
+
+        for (frame, file) in self.workspace_frames() {
+            let location = frame.location.as_ref().unwrap();
+
+            if spans
+                .iter()
+                .any(|span| span.file_name == file && span.line_start == location.line)
+            {
+                continue;
+            }
+
+            spans.push(span(
+                &file,
+                location,
+                Some(format!("called from `{}`", frame.function)),
+                spans.is_empty(),
+            ));
+        }
+
+        Diagnostic {
+            message: message.clone(),
+            code: Some(Code {
+                code: String::from("panic"),
+                explanation: None,
+            }),
+            level: Level::Error,
+            spans,
+            children: Vec::new(),
+            rendered: Some(format!("error: {}\n{}", message, self.render())),
+        }
+    }
+}
+
+// Parses the frames of a backtrace, frames are numbered and their location is on the following line
+fn parse_backtrace(lines: &[&str]) -> Vec<Frame> {
+    let mut frames: Vec<Frame> = Vec::new();
+
+    for line in lines {
+        let trimmed = line.trim();
+
+        if let Some(location) = trimmed.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                frame.location = Location::parse(location);
+            }
+            continue;
+        }
+
+        let Some((number, function)) = trimmed.split_once(": ") else {
+            continue;
+        };
+
+        if number.chars().all(|character| character.is_ascii_digit()) && !number.is_empty() {
+            frames.push(Frame {
+                function: function.to_string(),
+                location: None,
+            });
+        }
+    }
+
+    frames
+}
+
+// A span covering a single position, read from the file so the context builder can highlight it
+fn span(file: &str, location: &Location, label: Option<String>, is_primary: bool) -> Span {
+    Span {
+        file_name: file.to_string(),
+        byte_start: 0,
+        byte_end: 0,
+        line_start: location.line,
+        line_end: location.line,
+        column_start: location.column,
+        column_end: location.column + 1,
+        is_primary,
+        text: Vec::new(),
+        label,
+        suggested_replacement: None,
+        suggestion_applicability: None,
+        expansion: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(file: &str, line: usize, column: usize) -> Option<Location> {
+        Some(Location {
+            file: file.to_string(),
+            line,
+            column,
+        })
+    }
+
+    #[test]
+    fn parses_the_current_format() {
+        let output = "running 1 test\n\
+                      thread 'tests::parses' panicked at src/lib.rs:20:9:\n\
+                      assertion `left == right` failed\n  \
+                      left: 1\n \
+                      right: 2\n\
+                      note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(panic.thread, "tests::parses");
+        assert_eq!(panic.location, location("src/lib.rs", 20, 9));
+        assert_eq!(
+            panic.message,
+            "assertion `left == right` failed\n  left: 1\n right: 2"
+        );
+        assert!(panic.backtrace.is_empty());
+    }
+
+    #[test]
+    fn parses_the_format_with_a_thread_id() {
+        let output = "thread 'main' (10810) panicked at src/items.rs:4:10:\n\
+                      index out of bounds: the len is 3 but the index is 7\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(panic.thread, "main");
+        assert_eq!(panic.location, location("src/items.rs", 4, 10));
+        assert_eq!(
+            panic.message,
+            "index out of bounds: the len is 3 but the index is 7"
+        );
+    }
+
+    #[test]
+    fn parses_the_format_before_1_73() {
+        let output = "thread 'main' panicked at 'called `Option::unwrap()` on a `None` value', src/main.rs:5:37\n\
+                      note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(panic.thread, "main");
+        assert_eq!(panic.location, location("src/main.rs", 5, 37));
+        assert_eq!(panic.message, "called `Option::unwrap()` on a `None` value");
+    }
+
+    #[test]
+    fn parses_assertions_in_the_format_before_1_73() {
+        let output = "thread 'tests::adds' panicked at 'assertion failed: `(left == right)`\n  \
+                      left: `1`,\n \
+                      right: `2`', src/lib.rs:9:9\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(panic.location, location("src/lib.rs", 9, 9));
+        assert_eq!(
+            panic.message,
+            "assertion failed: `(left == right)`\n  left: `1`,\n right: `2`"
+        );
+    }
+
+    #[test]
+    fn parses_the_frames_of_a_backtrace() {
+        let output = "thread 'main' panicked at src/items.rs:4:10:\n\
+                      index out of bounds: the len is 3 but the index is 7\n\
+                      stack backtrace:\n   \
+                      0: rust_begin_unwind\n             \
+                      at /rustc/59807616e1fa2540724bfbac14d7976d7e4a3860/library/std/src/panicking.rs:645:5\n   \
+                      1: core::panicking::panic_bounds_check\n   \
+                      2: rr::items::pick\n             \
+                      at ./src/items.rs:4:5\n  \
+                      10: rr::main\n             \
+                      at ./src/main.rs:5:20\n\
+                      note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(
+            panic.backtrace,
+            vec![
+                Frame {
+                    function: String::from("rust_begin_unwind"),
+                    location: location(
+                        "/rustc/59807616e1fa2540724bfbac14d7976d7e4a3860/library/std/src/panicking.rs",
+                        645,
+                        5
+                    ),
+                },
+                Frame {
+                    function: String::from("core::panicking::panic_bounds_check"),
+                    location: None,
+                },
+                Frame {
+                    function: String::from("rr::items::pick"),
+                    location: location("./src/items.rs", 4, 5),
+                },
+                Frame {
+                    function: String::from("rr::main"),
+                    location: location("./src/main.rs", 5, 20),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_first_panic_among_other_output() {
+        let output = "Compiling rr v0.1.0\nhello\n\
+                      thread 'main' panicked at src/main.rs:2:5:\nfirst\n\
+                      thread 'other' panicked at src/main.rs:9:5:\nsecond\n";
+
+        let panic = Panic::parse(output).unwrap();
+
+        assert_eq!(panic.thread, "main");
+        assert!(panic.message.starts_with("first"));
+    }
+
+    #[test]
+    fn finds_nothing_without_a_panic() {
+        assert_eq!(Panic::parse("running 1 test\ntest it_works ... ok\n"), None);
+    }
+
+    #[test]
+    fn parses_locations_whose_path_contains_colons() {
+        assert_eq!(
+            Location::parse(r"C:\Users\me\rr\src\main.rs:5:20"),
+            location(r"C:\Users\me\rr\src\main.rs", 5, 20)
+        );
+        assert_eq!(Location::parse("src/main.rs:5"), None);
+    }
+
+    #[test]
+    fn diffs_the_sides_of_a_failed_assertion() {
+        let panic = Panic {
+            thread: String::from("main"),
+            location: None,
+            message: String::from(
+                "assertion `left == right` failed\n  left: [1, 2, 3]\n right: [1, 4, 3]",
+            ),
+            backtrace: Vec::new(),
+        };
+
+        assert_eq!(panic.assertion_diff().unwrap(), " [1,\n-2,\n+4,\n 3]");
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+};
+
+use crate::config::Config;
+
+use super::{
+    check::spawn_cargo,
+    diagnostic::{Diagnostic, Level},
+    panic::Panic,
+};
+
+/// The outcome of a `cargo run`
+#[derive(Debug, Clone, Default)]
+pub struct ProgramRun {
+    /// Errors from building the program, when there are any it didn't run
+    pub build_errors: Vec<Diagnostic>,
+
+    /// The panic the program ended with, if any
+    pub panic: Option<Panic>,
+
+    /// The exit code, `None` if the program didn't run or was killed by a signal
+    pub status: Option<i32>,
+}
+
+impl ProgramRun {
+    /// The panic as a diagnostic, or `None` when the program didn't panic or none of the
+    /// backtrace is in the crate's sources
+    pub fn panic_diagnostic(&self) -> Option<Diagnostic> {
+        let panic = self.panic.as_ref()?;
+
+        let headline = panic.message.lines().next().unwrap_or_default();
+        let diagnostic = panic.to_diagnostic(format!("the program panicked: {}", headline), None);
+
+        (!diagnostic.spans.is_empty()).then_some(diagnostic)
+    }
+
+    /// Everything neura should fix: build errors, then the panic
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.build_errors
+            .iter()
+            .cloned()
+            .chain(self.panic_diagnostic())
+            .collect()
+    }
+}
+
+/// Builds the program, then runs it with `args` and backtraces turned on.
+///
+/// The program keeps the terminal for its input and output, its stderr is echoed
+/// while it is read for a panic.
+pub fn spawn_run(args: &[String], config: &Config) -> ProgramRun {
+    let build_errors = spawn_cargo(&["build"], config);
+
+    if build_errors.iter().any(|error| error.level == Level::Error) {
+        return ProgramRun {
+            build_errors,
+            ..Default::default()
+        };
+    }
+
+    let mut child = Command::new("cargo")
+        .args(["run", "--quiet"])
+        .args(config.package_args())
+        .arg("--")
+        .args(args)
+        .env("RUST_BACKTRACE", "1")
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start cargo");
+
+    let mut stderr = String::new();
+
+    if let Some(ref mut output) = child.stderr {
+        for line in BufReader::new(output).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            eprintln!("{}", line);
+            stderr.push_str(&line);
+            stderr.push('\n');
+        }
+    }
+
+    let status = child.wait().expect("Failed to wait on child");
+
+    ProgramRun {
+        build_errors,
+        panic: Panic::parse(&stderr),
+        status: status.code(),
+    }
+}
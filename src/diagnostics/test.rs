@@ -0,0 +1,372 @@
+use serde::Deserialize;
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use crate::{config::Config, workspace::Workspace};
+
+use super::{
+    check::spawn_cargo,
+    diagnostic::{Diagnostic, Level},
+    panic::{Location, Panic},
+};
+
+// libtest's JSON output is unstable, stable toolchains refuse it with this message
+const NIGHTLY_ONLY: &str = "only accepted on the nightly compiler";
+
+/// A test that failed, along with what it printed
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// The full path of the test, e.g. `tests::it_works`
+    pub name: String,
+
+    /// The captured output of the test, which holds the panic
+    pub output: String,
+}
+
+impl Failure {
+    /// Turns the failure into a diagnostic anchored at the test, or `None` when neither the test
+    /// nor the panic can be found in the crate's sources
+    pub fn to_diagnostic(&self) -> Option<Diagnostic> {
+        let message = format!("test `{}` failed", self.name);
+
+        let panic = Panic::parse(&self.output).unwrap_or_else(|| Panic {
+            thread: self.name.clone(),
+            location: None,
+            message: self.output.trim().to_string(),
+            backtrace: Vec::new(),
+        });
+
+        let mut diagnostic = panic.to_diagnostic(message, self.location(&panic));
+
+        if diagnostic.spans.is_empty() {
+            return None;
+        }
+
+        // The test describes the expected behaviour, so it's the code under test that should change
+        diagnostic.rendered = diagnostic.rendered.map(|rendered| {
+            format!(
+                "{}\nnote: fix the code under test, only change the test itself if it is clearly wrong",
+                rendered
+            )
+        });
+
+        Some(diagnostic)
+    }
+
+    // Where the test function is: its frame in the backtrace, or its definition in the sources
+    fn location(&self, panic: &Panic) -> Option<Location> {
+        let frame = panic.backtrace.iter().find(|frame| {
+            let function = frame.function.trim_end_matches("::{{closure}}");
+            function == self.name || function.ends_with(&format!("::{}", self.name))
+        });
+
+        if let Some(location) = frame.and_then(|frame| frame.location.clone()) {
+            return Some(location);
+        }
+
+        let function = self.name.rsplit("::").next()?;
+        let signature = format!("fn {}(", function);
+
+        for file in Workspace::current().rust_files() {
+            let Ok(content) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+
+            let lines: Vec<&str> = content.lines().collect();
+
+            for (index, line) in lines.iter().enumerate() {
+                let Some(column) = line.find(&signature) else {
+                    continue;
+                };
+
+                // Helpers in other modules can share the name, tests are marked as such right above
+                if !lines[index.saturating_sub(3)..index]
+                    .iter()
+                    .any(|line| line.contains("test"))
+                {
+                    continue;
+                }
+
+                return Some(Location {
+                    file,
+                    line: index + 1,
+                    column: column + 1,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// The outcome of a `cargo test` run
+#[derive(Debug, Clone, Default)]
+pub struct TestRun {
+    /// Errors from building the tests, when there are any the tests didn't run
+    pub build_errors: Vec<Diagnostic>,
+
+    pub failures: Vec<Failure>,
+}
+
+impl TestRun {
+    /// Everything neura should fix: build errors first, then the failing tests
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.build_errors
+            .iter()
+            .cloned()
+            .chain(
+                self.failures
+                    .iter()
+                    .filter_map(|failure| failure.to_diagnostic()),
+            )
+            .collect()
+    }
+
+    /// Failing tests that can't be traced back to the crate's sources
+    pub fn untraceable(&self) -> Vec<&Failure> {
+        self.failures
+            .iter()
+            .filter(|failure| failure.to_diagnostic().is_none())
+            .collect()
+    }
+}
+
+/// A line of libtest's `--format json` output
+#[derive(Deserialize)]
+struct TestEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    stdout: Option<String>,
+}
+
+/// Builds the tests, then runs them with backtraces turned on.
+///
+/// `filters` are exact test names to run, every test runs when it's empty.
+pub fn spawn_tests(filters: &[String], config: &Config) -> TestRun {
+    let build_errors = spawn_cargo(&["test", "--no-run"], config);
+
+    if build_errors.iter().any(|error| error.level == Level::Error) {
+        return TestRun {
+            build_errors,
+            failures: Vec::new(),
+        };
+    }
+
+    let (stdout, stderr) = run_tests(filters, config, true);
+
+    let failures = if stdout.contains(NIGHTLY_ONLY) || stderr.contains(NIGHTLY_ONLY) {
+        parse_human(&run_tests(filters, config, false).0)
+    } else {
+        parse_json(&stdout)
+    };
+
+    TestRun {
+        build_errors,
+        failures,
+    }
+}
+
+/// Runs the tests behind a spinner
+pub fn tests_with_spinner(filters: &[String], config: &Config) -> TestRun {
+    let spinner = indicatif::ProgressBar::new_spinner();
+    spinner.set_message(if filters.is_empty() {
+        String::from("🧪 Running `cargo test` ...")
+    } else {
+        format!(
+            "🧪 Running {} failing {} ...",
+            filters.len(),
+            if filters.len() == 1 { "test" } else { "tests" }
+        )
+    });
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let run = spawn_tests(filters, config);
+    spinner.finish_and_clear();
+
+    run
+}
+
+// Runs `cargo test`, returning its stdout and stderr
+fn run_tests(filters: &[String], config: &Config, json: bool) -> (String, String) {
+    let mut command = Command::new("cargo");
+
+    // Every failure is wanted, not just those of the first test binary that fails
+    command
+        .args(["test", "--no-fail-fast"])
+        .args(config.package_args())
+        .arg("--");
+
+    if !filters.is_empty() {
+        command.arg("--exact").args(filters);
+    }
+
+    if json {
+        command.args(["-Z", "unstable-options", "--format", "json"]);
+    }
+
+    let output = command
+        .env("RUST_BACKTRACE", "1")
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to start cargo");
+
+    (
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+}
+
+// Collects the failed tests from libtest's JSON events
+fn parse_json(stdout: &str) -> Vec<Failure> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<TestEvent>(line).ok())
+        .filter(|event| event.kind == "test" && event.event == "failed")
+        .filter_map(|event| {
+            Some(Failure {
+                name: event.name?,
+                output: event.stdout.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+// Collects the failed tests from libtest's human output:
+// `test name ... FAILED` lines, and a `---- name stdout ----` section with the output of each
+fn parse_human(stdout: &str) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = stdout
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("test ")?
+                .strip_suffix(" ... FAILED")
+                .map(|name| Failure {
+                    name: name.to_string(),
+                    output: String::new(),
+                })
+        })
+        .collect();
+
+    let mut current: Option<usize> = None;
+
+    for line in stdout.lines() {
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|line| line.strip_suffix(" stdout ----"))
+        {
+            current = failures.iter().position(|failure| failure.name == name);
+            continue;
+        }
+
+        // The list of names that closes the report
+        if line == "failures:" {
+            current = None;
+            continue;
+        }
+
+        if let Some(index) = current {
+            failures[index].output.push_str(line);
+            failures[index].output.push('\n');
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HUMAN: &str = "
+running 4 tests
+test tests::adds ... ok
+test tests::divides ... FAILED
+test tests::ignored ... ignored
+test tests::parses ... FAILED
+
+failures:
+
+---- tests::divides stdout ----
+
+thread 'tests::divides' panicked at src/lib.rs:12:5:
+attempt to divide by zero
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+---- tests::parses stdout ----
+thread 'tests::parses' panicked at src/lib.rs:20:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+
+
+failures:
+    tests::divides
+    tests::parses
+
+test result: FAILED. 1 passed; 2 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+
+    #[test]
+    fn collects_failed_tests_with_their_output() {
+        let failures = parse_human(HUMAN);
+
+        assert_eq!(
+            failures
+                .iter()
+                .map(|failure| failure.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["tests::divides", "tests::parses"]
+        );
+        assert_eq!(
+            failures[0].output,
+            "\nthread 'tests::divides' panicked at src/lib.rs:12:5:\n\
+             attempt to divide by zero\n\
+             note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n\n"
+        );
+        assert!(failures[1].output.contains(" right: 2\n"));
+        assert!(!failures[1].output.contains("tests::divides"));
+    }
+
+    #[test]
+    fn keeps_failures_without_captured_output() {
+        let failures = parse_human("test it_works ... FAILED\n\nfailures:\n    it_works\n");
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, "it_works");
+        assert!(failures[0].output.is_empty());
+    }
+
+    #[test]
+    fn finds_nothing_when_every_test_passes() {
+        let stdout =
+            "running 1 test\ntest it_works ... ok\n\ntest result: ok. 1 passed; 0 failed\n";
+
+        assert!(parse_human(stdout).is_empty());
+    }
+
+    #[test]
+    fn ignores_output_of_tests_that_did_not_fail() {
+        let stdout = "test a ... FAILED\n\n---- b stdout ----\nnoise\n---- a stdout ----\nsignal\n";
+
+        let failures = parse_human(stdout);
+
+        assert_eq!(failures[0].output, "signal\n");
+    }
+
+    #[test]
+    fn collects_failed_tests_from_json_events() {
+        let stdout = r#"{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "tests::adds" }
+{ "type": "test", "name": "tests::adds", "event": "ok" }
+{ "type": "test", "name": "tests::divides", "event": "failed", "stdout": "thread 'tests::divides' panicked at src/lib.rs:12:5:\nattempt to divide by zero\n" }
+{ "type": "suite", "event": "failed", "passed": 1, "failed": 1 }"#;
+
+        let failures = parse_json(stdout);
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, "tests::divides");
+        assert!(failures[0].output.starts_with("thread 'tests::divides'"));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    config::Config,
+    diagnostics::diagnostic::Diagnostic,
+    models::model::Model,
+    providers::provider::{Message, Provider},
+};
+
+use super::{context::ContextBuilder, prompt};
+
+// Tokens the API adds around every message for its role and separators
+const MESSAGE_OVERHEAD: usize = 4;
+
+// Appended to the error text when it had to be cut short
+const TRUNCATED: &str = "\n... (truncated)";
+
+/// Why a prompt couldn't be built
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TooLarge {
+    /// Tokens the prompt needs without any code
+    pub required: usize,
+
+    /// Tokens the model has left for the prompt after reserving room for the response
+    pub available: usize,
+}
+
+/// Builds the next message for `targets`, a cluster of related errors, so the whole conversation fits in the model's context window.
+///
+/// Room for the response is reserved first. What is left goes to, in order:
+/// the instructions and the error, the earlier messages of the conversation, and the source code.
+/// When it gets tight, the source code is trimmed first, then the oldest attempts are dropped,
+/// and finally the error text is cut short.
+pub fn fit_prompt(
+    config: &Config,
+    provider: &dyn Provider,
+    model: Model,
+    history: &mut Vec<Message>,
+    targets: &[&Diagnostic],
+    feedback: Option<&str>,
+) -> Result<String, TooLarge> {
+    let tools = provider.supports_tools();
+
+    // The tool definition is sent along with every request and counts against the window too
+    let tool_tokens = if tools {
+        provider.count_tokens(&prompt::changes_tool().parameters.to_string())
+    } else {
+        0
+    };
+
+    let build = |error: &str, context: &str| match feedback {
+        Some(feedback) => prompt::retry(feedback, error, context, tools),
+        None => prompt::fix(error, context, tools),
+    };
+
+    fit(
+        config,
+        provider,
+        model,
+        history,
+        targets,
+        tool_tokens,
+        &build,
+    )
+}
+
+/// Builds a request to explain `targets` so it fits in the model's context window, the same way `fit_prompt` does
+pub fn fit_explanation(
+    config: &Config,
+    provider: &dyn Provider,
+    model: Model,
+    targets: &[&Diagnostic],
+) -> Result<String, TooLarge> {
+    fit(
+        config,
+        provider,
+        model,
+        &mut Vec::new(),
+        targets,
+        0,
+        &prompt::explain,
+    )
+}
+
+// Fits the message made by `build` from the error text and the code around it, along with `history`,
+// in what's left of the window after the response and `reserved` tokens
+fn fit(
+    config: &Config,
+    provider: &dyn Provider,
+    model: Model,
+    history: &mut Vec<Message>,
+    targets: &[&Diagnostic],
+    reserved: usize,
+    build: &dyn Fn(&str, &str) -> String,
+) -> Result<String, TooLarge> {
+    let available = model
+        .context_window()
+        .saturating_sub(model.max_output_tokens())
+        .saturating_sub(reserved);
+
+    let mut error = targets
+        .iter()
+        .map(|target| target.rendered())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // Earlier attempts are dropped in pairs, the question along with its answer
+    while !history.is_empty()
+        && tokens(provider, history) + message_tokens(provider, &build(&error, "")) > available
+    {
+        history.drain(..history.len().min(2));
+    }
+
+    let required = message_tokens(provider, &build(&error, ""));
+
+    if required > available {
+        error = truncate(provider, &error, required - available).ok_or(TooLarge {
+            required,
+            available,
+        })?;
+    }
+
+    let remaining = available
+        .saturating_sub(tokens(provider, history))
+        .saturating_sub(message_tokens(provider, &build(&error, "")));
+
+    let context = ContextBuilder::new(
+        provider,
+        &config.context,
+        remaining.min(config.context.max_tokens),
+    )
+    .build(targets);
+
+    Ok(build(&error, &context))
+}
+
+/// Tokens taken up by a conversation
+pub fn tokens(provider: &dyn Provider, messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| message_tokens(provider, &message.content))
+        .sum()
+}
+
+fn message_tokens(provider: &dyn Provider, content: &str) -> usize {
+    provider.count_tokens(content) + MESSAGE_OVERHEAD
+}
+
+// Drops whole lines from the end of the error until it is `excess` tokens shorter.
+// The first line is always kept, without it there's nothing left to fix.
+fn truncate(provider: &dyn Provider, error: &str, excess: usize) -> Option<String> {
+    let lines: Vec<&str> = error.lines().collect();
+    let target = provider.count_tokens(error).checked_sub(excess)?;
+
+    (1..lines.len())
+        .rev()
+        .map(|keep| format!("{}{}", lines[..keep].join("\n"), TRUNCATED))
+        .find(|truncated| provider.count_tokens(truncated) <= target)
+}
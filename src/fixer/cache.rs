@@ -0,0 +1,145 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    constants::paths::DATA_DIRECTORY,
+    providers::provider::{Message, Tool},
+};
+
+use super::prompt::PROMPT_VERSION;
+
+/// An answer as it's stored on disk
+#[derive(Serialize, Deserialize)]
+struct Cached {
+    /// Seconds since the Unix epoch
+    created: u64,
+    content: String,
+}
+
+/// The model's answers to earlier prompts, stored in `.neura/cache` with one file per prompt.
+///
+/// An error that comes back during a watch session gets the same prompt, so the answer it got
+/// last time is reused instead of paid for again. Only answers whose fix was kept are stored,
+/// so one that was rolled back or turned down is never replayed.
+pub struct ResponseCache {
+    directory: PathBuf,
+    model: String,
+    ttl: Duration,
+
+    /// In bytes
+    max_size: u64,
+}
+
+impl ResponseCache {
+    /// The cache described by `config`, `None` when it's turned off
+    pub fn new(config: &Config) -> Option<Self> {
+        config.cache.enabled.then(|| Self {
+            directory: Path::new(DATA_DIRECTORY).join("cache"),
+            model: config.model_name(),
+            ttl: Duration::from_secs(config.cache.ttl_hours * 3600),
+            max_size: config.cache.max_size_mb * 1024 * 1024,
+        })
+    }
+
+    /// Identifies a prompt: the conversation with the diagnostic and its context, the model it's sent to,
+    /// the tool it has to answer with and the version of the prompts
+    pub fn key(&self, messages: &[Message], tool: Option<&Tool>) -> String {
+        sha256::digest(format!(
+            "{}:{}:{}:{}",
+            PROMPT_VERSION,
+            self.model,
+            tool.map(|tool| format!("{}{}", tool.name, tool.parameters))
+                .unwrap_or_default(),
+            serde_json::to_string(messages).unwrap_or_default()
+        ))
+    }
+
+    /// The answer stored for `key`, unless it has expired
+    pub fn get(&self, key: &str) -> Option<String> {
+        let json = std::fs::read_to_string(self.path(key)).ok()?;
+        let cached: Cached = serde_json::from_str(&json).ok()?;
+
+        if now().saturating_sub(cached.created) > self.ttl.as_secs() {
+            self.remove(key);
+            return None;
+        }
+
+        Some(cached.content)
+    }
+
+    /// Stores the answer to the prompt `key` identifies, dropping the oldest answers when the cache grows too large
+    pub fn put(&self, key: &str, content: &str) {
+        let cached = Cached {
+            created: now(),
+            content: content.to_string(),
+        };
+
+        let Ok(json) = serde_json::to_string(&cached) else {
+            return;
+        };
+
+        // A cache that can't be written only means paying for the same answer again
+        if std::fs::create_dir_all(&self.directory).is_err()
+            || std::fs::write(self.path(key), json).is_err()
+        {
+            return;
+        }
+
+        self.prune();
+    }
+
+    /// Forgets the answer to a prompt, e.g. when it turned out to be unusable
+    pub fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+
+    // Removes expired answers, then the oldest ones until the cache fits in `max_size`
+    fn prune(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.directory) else {
+            return;
+        };
+
+        let mut files: Vec<(PathBuf, SystemTime, u64)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+
+        // Newest first, so whatever is over the limit is at the end
+        files.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+
+        let mut size = 0;
+
+        for (path, modified, len) in files {
+            if modified.elapsed().is_ok_and(|age| age > self.ttl) {
+                let _ = std::fs::remove_file(path);
+                continue;
+            }
+
+            size += len;
+
+            if size > self.max_size {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,163 @@
+use colored::Colorize;
+use linked_hash_map::LinkedHashMap;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    patch::{hunk::FilePatch, review::Review, rollback::Snapshot},
+    workspace::Workspace,
+};
+
+#[derive(Deserialize)]
+pub struct Change {
+    pub file: String,
+    pub diff: String,
+
+    /// Only used to estimate savings, so models that leave it out aren't punished
+    #[serde(default)]
+    pub time_estimate_seconds: u64,
+}
+
+#[derive(Deserialize)]
+pub struct Changes {
+    pub changes: Vec<Change>,
+}
+
+/// The outcome of writing a set of changes to disk
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// Files as they were before anything was written
+    pub snapshot: Snapshot,
+
+    /// Time it would have taken to make the applied changes by hand
+    pub estimated_time: u64,
+
+    /// Changes that could not be applied, and why
+    pub problems: Vec<String>,
+
+    /// Whether the user turned down at least one change during review
+    pub rejected: bool,
+}
+
+/// Applies the changes returned by the model.
+pub fn apply_changes(config: &Config, changes: &[Change]) -> Applied {
+    let mut applied = Applied::default();
+
+    // Time estimates are per change, but patches are per file
+    let mut estimated_times: LinkedHashMap<String, u64> = LinkedHashMap::new();
+
+    // All hunks for a file are applied together, so they can't shift line numbers under each other
+    let mut patches: LinkedHashMap<String, FilePatch> = LinkedHashMap::new();
+
+    for change in changes {
+        // Dependencies and build output are never edited, even if the model asks to
+        if !Workspace::current().is_editable(&change.file) {
+            println!(
+                "{} Skipping change to {}, it isn't part of a workspace member",
+                ">".bright_black(),
+                change.file.bright_yellow()
+            );
+            applied.problems.push(format!(
+                "{} is not part of a workspace member and can't be edited",
+                change.file
+            ));
+            continue;
+        }
+
+        match FilePatch::parse(&change.file, &change.diff) {
+            Ok(patch) => {
+                patches
+                    .entry(change.file.clone())
+                    .or_insert_with(|| FilePatch {
+                        file: change.file.clone(),
+                        hunks: Vec::new(),
+                    })
+                    .hunks
+                    .extend(patch.hunks);
+
+                *estimated_times.entry(change.file.clone()).or_insert(0) +=
+                    change.time_estimate_seconds;
+            }
+            Err(err) => {
+                println!("{} Skipping change: {}", ">".bright_black(), err);
+                applied.problems.push(err.to_string());
+            }
+        }
+    }
+
+    let mut review = Review::new(config.auto_apply);
+
+    for (file, mut patch) in patches {
+        // Hunks from separate changes may be out of order, which only matters if we know where they go
+        if patch.hunks.iter().all(|hunk| hunk.old_start.is_some()) {
+            patch.hunks.sort_by_key(|hunk| hunk.old_start);
+        }
+
+        let contents = match std::fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                println!("{} Unable to read {}: {}", ">".bright_black(), file, err);
+                applied
+                    .problems
+                    .push(format!("unable to read {}: {}", file, err));
+                continue;
+            }
+        };
+
+        // Conflicts are caught before the user is asked to review anything
+        if let Err(err) = patch.apply(&contents) {
+            println!("{} Conflict: {}", ">".bright_black(), err);
+            applied.problems.push(err.to_string());
+            continue;
+        }
+
+        let total_hunks = patch.hunks.len();
+
+        let Some(patch) = review.review(&patch) else {
+            println!("{} Skipping {}", ">".bright_black(), file.bright_yellow());
+            applied.rejected = true;
+            continue;
+        };
+
+        if patch.hunks.len() < total_hunks {
+            applied.rejected = true;
+        }
+
+        match patch.apply(&contents) {
+            Ok(patched) => {
+                if let Err(err) = std::fs::write(&file, patched) {
+                    println!("{} Unable to write {}: {}", ">".bright_black(), file, err);
+                    applied
+                        .problems
+                        .push(format!("unable to write {}: {}", file, err));
+
+                    // A write that failed halfway may have left the file truncated
+                    let _ = std::fs::write(&file, &contents);
+                    continue;
+                }
+
+                println!(
+                    "{} Editing {}, {} {}",
+                    ">".bright_black(),
+                    file.bright_yellow(),
+                    patch.hunks.len(),
+                    if patch.hunks.len() == 1 {
+                        "hunk"
+                    } else {
+                        "hunks"
+                    }
+                );
+
+                applied.snapshot.record_contents(&file, Some(contents));
+
+                applied.estimated_time += estimated_times.get(&file).copied().unwrap_or(0);
+            }
+            Err(err) => {
+                println!("{} Conflict: {}", ">".bright_black(), err);
+                applied.problems.push(err.to_string());
+            }
+        }
+    }
+
+    applied
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+
+use crate::diagnostics::diagnostic::Diagnostic;
+
+use super::context::enclosing_item;
+
+// Codes for a name that can't be resolved, every use of a misspelled name reports one of these
+const RESOLUTION_CODES: [&str; 6] = ["E0412", "E0422", "E0423", "E0425", "E0432", "E0433"];
+
+/// Errors that most likely share a cause and are fixed with a single request
+#[derive(Debug, Clone)]
+pub struct Cluster<'t> {
+    pub diagnostics: Vec<&'t Diagnostic>,
+}
+
+impl<'t> Cluster<'t> {
+    /// The file every error of the cluster is in
+    pub fn file(&self) -> &str {
+        self.diagnostics[0].file().unwrap_or_default()
+    }
+
+    /// The first error's headline, and how many others come with it
+    pub fn headline(&self) -> String {
+        match self.diagnostics.len() {
+            1 => self.diagnostics[0].headline(),
+            len => format!(
+                "{} (and {} related)",
+                self.diagnostics[0].headline(),
+                len - 1
+            ),
+        }
+    }
+
+    /// The errors of the cluster that `errors` still reports, as they are reported now
+    pub fn remaining<'e>(&self, errors: &'e [Diagnostic]) -> Vec<&'e Diagnostic> {
+        let mut remaining: Vec<&Diagnostic> = Vec::new();
+
+        // Identical errors are the same by `is_same`, so each one is only matched once
+        for diagnostic in &self.diagnostics {
+            if let Some(error) = errors.iter().find(|error| {
+                error.is_same(diagnostic)
+                    && !remaining.iter().any(|taken| std::ptr::eq(*taken, *error))
+            }) {
+                remaining.push(error);
+            }
+        }
+
+        remaining
+    }
+
+    /// Lists the errors that are about to be sent together
+    pub fn show(&self) {
+        println!(
+            "🧩 Fixing {} related errors in {} together:",
+            self.diagnostics.len().to_string().bright_red(),
+            self.file().bright_yellow()
+        );
+
+        for diagnostic in &self.diagnostics {
+            println!(
+                "{} line {}: {}",
+                ">".bright_black(),
+                diagnostic.line().unwrap_or_default(),
+                diagnostic.headline()
+            );
+        }
+    }
+}
+
+/// Splits `diagnostics` into clusters of related errors, keeping their order.
+///
+/// Errors are related when they are in the same file and either sit in the same item,
+/// fail to resolve the same name, or point at overlapping lines.
+pub fn cluster<'t>(diagnostics: &[&'t Diagnostic]) -> Vec<Cluster<'t>> {
+    let items = enclosing_items(diagnostics);
+
+    // Union-find over the diagnostics
+    let mut parents: Vec<usize> = (0..diagnostics.len()).collect();
+
+    fn root(parents: &mut [usize], index: usize) -> usize {
+        let mut index = index;
+
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+
+        index
+    }
+
+    for a in 0..diagnostics.len() {
+        for b in a + 1..diagnostics.len() {
+            let same_item = items[a].is_some() && items[a] == items[b];
+
+            if diagnostics[a].file() == diagnostics[b].file()
+                && (same_item
+                    || same_unresolved_name(diagnostics[a], diagnostics[b])
+                    || overlap(diagnostics[a], diagnostics[b]))
+            {
+                let (root_a, root_b) = (root(&mut parents, a), root(&mut parents, b));
+                parents[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut cluster_of_root: HashMap<usize, usize> = HashMap::new();
+
+    for (index, diagnostic) in diagnostics.iter().enumerate() {
+        let root = root(&mut parents, index);
+
+        match cluster_of_root.get(&root) {
+            Some(cluster) => clusters[*cluster].diagnostics.push(diagnostic),
+            None => {
+                cluster_of_root.insert(root, clusters.len());
+                clusters.push(Cluster {
+                    diagnostics: vec![diagnostic],
+                });
+            }
+        }
+    }
+
+    clusters
+}
+
+/// Every diagnostic in a cluster of its own
+pub fn singletons<'t>(diagnostics: &[&'t Diagnostic]) -> Vec<Cluster<'t>> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| Cluster {
+            diagnostics: vec![diagnostic],
+        })
+        .collect()
+}
+
+// The file and lines of the item each diagnostic is in, each file is only read once
+fn enclosing_items(diagnostics: &[&Diagnostic]) -> Vec<Option<(String, usize, usize)>> {
+    let mut files: HashMap<String, Vec<String>> = HashMap::new();
+
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let file = diagnostic.file()?;
+            let line = diagnostic.line()?;
+
+            let lines = files.entry(file.to_string()).or_insert_with(|| {
+                std::fs::read_to_string(file)
+                    .unwrap_or_default()
+                    .lines()
+                    .map(String::from)
+                    .collect()
+            });
+
+            let (start, end) = enclosing_item(lines, line)?;
+
+            Some((file.to_string(), start, end))
+        })
+        .collect()
+}
+
+// Whether both failed to resolve the same name, e.g. every use of a misspelled variable
+fn same_unresolved_name(a: &Diagnostic, b: &Diagnostic) -> bool {
+    let resolution_code = |diagnostic: &Diagnostic| {
+        diagnostic
+            .code
+            .as_ref()
+            .filter(|code| RESOLUTION_CODES.contains(&code.code.as_str()))
+            .is_some()
+    };
+
+    resolution_code(a)
+        && resolution_code(b)
+        && quoted_name(a).is_some()
+        && quoted_name(a) == quoted_name(b)
+}
+
+// The first name in backticks in the message, e.g. `foo` in "cannot find value `foo` in this scope"
+fn quoted_name(diagnostic: &Diagnostic) -> Option<&str> {
+    diagnostic.message.split('`').nth(1)
+}
+
+// Whether the primary spans share a line
+fn overlap(a: &Diagnostic, b: &Diagnostic) -> bool {
+    match (a.primary_span(), b.primary_span()) {
+        (Some(a), Some(b)) => a.line_start <= b.line_end && b.line_start <= a.line_end,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::diagnostic::{Code, Level, Span};
+
+    use super::*;
+
+    fn error(file: &str, line: usize, code: Option<&str>, message: &str) -> Diagnostic {
+        Diagnostic {
+            message: message.to_string(),
+            code: code.map(|code| Code {
+                code: code.to_string(),
+                explanation: None,
+            }),
+            level: Level::Error,
+            spans: vec![Span {
+                file_name: file.to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                line_start: line,
+                line_end: line,
+                column_start: 1,
+                column_end: 2,
+                is_primary: true,
+                text: Vec::new(),
+                label: None,
+                suggested_replacement: None,
+                suggestion_applicability: None,
+                expansion: None,
+            }],
+            children: Vec::new(),
+            rendered: None,
+        }
+    }
+
+    // The lines of each cluster's errors
+    fn lines(clusters: &[Cluster]) -> Vec<Vec<usize>> {
+        clusters
+            .iter()
+            .map(|cluster| {
+                cluster
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.line().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_every_use_of_the_same_unresolved_name() {
+        let errors = [
+            error(
+                "missing.rs",
+                3,
+                Some("E0425"),
+                "cannot find value `cout` in this scope",
+            ),
+            error("missing.rs", 40, Some("E0308"), "mismatched types"),
+            error(
+                "missing.rs",
+                80,
+                Some("E0425"),
+                "cannot find value `cout` in this scope",
+            ),
+            error(
+                "missing.rs",
+                120,
+                Some("E0425"),
+                "cannot find value `count` in this scope",
+            ),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+        assert_eq!(
+            lines(&cluster(&targets)),
+            vec![vec![3, 80], vec![40], vec![120]]
+        );
+    }
+
+    #[test]
+    fn groups_errors_on_overlapping_lines() {
+        let mut spanning = error("missing.rs", 10, None, "borrow of moved value");
+        spanning.spans[0].line_end = 12;
+
+        let errors = [
+            spanning,
+            error("missing.rs", 12, Some("E0308"), "mismatched types"),
+            error("missing.rs", 13, Some("E0308"), "mismatched types"),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+        assert_eq!(lines(&cluster(&targets)), vec![vec![10, 12], vec![13]]);
+    }
+
+    #[test]
+    fn joins_groups_transitively() {
+        let mut first = error("missing.rs", 1, None, "a");
+        first.spans[0].line_end = 2;
+        let mut second = error("missing.rs", 2, None, "b");
+        second.spans[0].line_end = 3;
+
+        // The first and the last don't share a line, but both share one with the second
+        let errors = [
+            first,
+            error("missing.rs", 3, None, "c"),
+            second,
+            error("missing.rs", 50, None, "d"),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+        assert_eq!(lines(&cluster(&targets)), vec![vec![1, 3, 2], vec![50]]);
+    }
+
+    #[test]
+    fn never_groups_errors_from_different_files() {
+        let errors = [
+            error(
+                "a.rs",
+                3,
+                Some("E0425"),
+                "cannot find value `x` in this scope",
+            ),
+            error(
+                "b.rs",
+                3,
+                Some("E0425"),
+                "cannot find value `x` in this scope",
+            ),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+        assert_eq!(cluster(&targets).len(), 2);
+    }
+
+    #[test]
+    fn groups_errors_in_the_same_item() {
+        let file = std::env::temp_dir().join(format!("neura-cluster-{}.rs", std::process::id()));
+        std::fs::write(
+            &file,
+            "fn first() {\n    let a = 1;\n    let b = 2;\n}\n\nfn second() {\n    let c = 3;\n}\n",
+        )
+        .unwrap();
+        let file_name = file.to_string_lossy();
+
+        let errors = [
+            error(&file_name, 2, Some("E0308"), "mismatched types"),
+            error(&file_name, 7, Some("E0308"), "mismatched types"),
+            error(&file_name, 3, Some("E0599"), "no method named `len` found"),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+        let clusters = cluster(&targets);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(lines(&clusters), vec![vec![2, 3], vec![7]]);
+        assert_eq!(
+            clusters[0].headline(),
+            "error[E0308]: mismatched types (and 1 related)"
+        );
+    }
+
+    #[test]
+    fn matches_remaining_errors_once_each() {
+        let errors = [
+            error(
+                "a.rs",
+                3,
+                Some("E0425"),
+                "cannot find value `x` in this scope",
+            ),
+            error(
+                "a.rs",
+                9,
+                Some("E0425"),
+                "cannot find value `x` in this scope",
+            ),
+        ];
+        let targets: Vec<&Diagnostic> = errors.iter().collect();
+        let clusters = cluster(&targets);
+
+        // One of the two is fixed, and the other one has moved
+        let now = [error(
+            "a.rs",
+            4,
+            Some("E0425"),
+            "cannot find value `x` in this scope",
+        )];
+
+        let remaining = clusters[0].remaining(&now);
+
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].line(), Some(4));
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::ContextConfig,
+    diagnostics::diagnostic::{Diagnostic, Span},
+    providers::provider::Provider,
+    workspace::Workspace,
+};
+
+// Keywords that start an item once visibility and qualifiers are stripped
+const ITEM_KEYWORDS: [&str; 8] = [
+    "fn ",
+    "impl",
+    "trait ",
+    "struct ",
+    "enum ",
+    "mod ",
+    "union ",
+    "macro_rules!",
+];
+
+// Keywords that can come before the item keyword
+const QUALIFIERS: [&str; 7] = [
+    "pub(crate) ",
+    "pub(super) ",
+    "pub ",
+    "async ",
+    "const ",
+    "unsafe ",
+    "default ",
+];
+
+// Keywords that start a type definition
+const DEFINITION_KEYWORDS: [&str; 5] = ["struct ", "enum ", "trait ", "type ", "union "];
+
+// Names that show up in diagnostics all the time but are never defined in the crate
+const STD_NAMES: [&str; 12] = [
+    "Self", "String", "Vec", "Option", "Result", "Box", "Some", "None", "Ok", "Err", "Rc", "Arc",
+];
+
+// Items longer than this are never pulled in whole, their windows are enough
+const MAX_ITEM_LINES: usize = 400;
+
+/// A range of lines from a file
+#[derive(Debug, Clone, PartialEq)]
+struct Snippet {
+    file: String,
+
+    /// 1-based and inclusive
+    start: usize,
+    end: usize,
+}
+
+impl Snippet {
+    // Whether the snippets overlap or touch, so they can be shown as one
+    fn touches(&self, other: &Snippet) -> bool {
+        self.file == other.file && self.start <= other.end + 1 && other.start <= self.end + 1
+    }
+}
+
+/// Collects the source code the model needs to see to fix a diagnostic, within a token budget
+pub struct ContextBuilder<'a> {
+    provider: &'a dyn Provider,
+    config: &'a ContextConfig,
+    max_tokens: usize,
+    files: HashMap<String, Option<Vec<String>>>,
+    snippets: Vec<Snippet>,
+}
+
+impl<'a> ContextBuilder<'a> {
+    pub fn new(provider: &'a dyn Provider, config: &'a ContextConfig, max_tokens: usize) -> Self {
+        Self {
+            provider,
+            config,
+            max_tokens,
+            files: HashMap::new(),
+            snippets: Vec::new(),
+        }
+    }
+
+    /// Builds the context for related `diagnostics`, from most to least important:
+    /// the code around each error, the items they are in, the code around related spans,
+    /// and the definitions of types they mention.
+    pub fn build(mut self, diagnostics: &[&Diagnostic]) -> String {
+        let primaries: Vec<&Span> = diagnostics
+            .iter()
+            .filter_map(|diagnostic| diagnostic.primary_span().map(Span::invocation))
+            .collect();
+
+        // The code right around each error goes in first, shrinking the window until it fits
+        for primary in &primaries {
+            let mut window = self.config.window;
+
+            while let Some(snippet) = self.window(primary, window) {
+                if self.try_add(snippet) || window == 0 {
+                    break;
+                }
+
+                window /= 2;
+            }
+        }
+
+        for primary in &primaries {
+            if let Some(item) = self.enclosing_item(&primary.file_name, primary.line_start) {
+                self.try_add(item);
+            }
+        }
+
+        for span in diagnostics
+            .iter()
+            .flat_map(|diagnostic| diagnostic.all_spans())
+        {
+            let span = span.invocation();
+
+            if primaries.contains(&span) {
+                continue;
+            }
+
+            if let Some(snippet) = self.window(span, self.config.window / 2) {
+                self.try_add(snippet);
+            }
+        }
+
+        let mut names: Vec<String> = Vec::new();
+
+        for diagnostic in diagnostics {
+            for name in referenced_names(diagnostic) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        for name in names {
+            if let Some(definition) = self.definition(&name) {
+                self.try_add(definition);
+            }
+        }
+
+        self.render()
+    }
+
+    // Adds a snippet if the context stays within budget, returning whether it was added
+    fn try_add(&mut self, snippet: Snippet) -> bool {
+        let previous = self.snippets.clone();
+
+        self.snippets.push(snippet);
+        self.merge();
+
+        if self.provider.count_tokens(&self.render()) <= self.max_tokens {
+            return true;
+        }
+
+        self.snippets = previous;
+
+        false
+    }
+
+    // Joins snippets that overlap, keeping the order they were added in per file
+    fn merge(&mut self) {
+        let mut merged: Vec<Snippet> = Vec::new();
+
+        for snippet in self.snippets.drain(..) {
+            match merged
+                .iter_mut()
+                .find(|existing| existing.touches(&snippet))
+            {
+                Some(existing) => {
+                    existing.start = existing.start.min(snippet.start);
+                    existing.end = existing.end.max(snippet.end);
+                }
+                None => merged.push(snippet),
+            }
+        }
+
+        // Merging can make earlier snippets touch, so keep going until nothing changes
+        let changed = merged
+            .iter()
+            .enumerate()
+            .any(|(i, a)| merged.iter().skip(i + 1).any(|b| a.touches(b)));
+
+        self.snippets = merged;
+
+        if changed {
+            self.merge();
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let mut output = String::new();
+
+        let snippets = self.snippets.clone();
+
+        for snippet in snippets {
+            let Some(lines) = self.lines(&snippet.file) else {
+                continue;
+            };
+
+            output.push_str(&format!(
+                "--- {} (lines {}-{}) ---\n",
+                snippet.file, snippet.start, snippet.end
+            ));
+
+            for (index, line) in lines
+                .iter()
+                .enumerate()
+                .take(snippet.end)
+                .skip(snippet.start - 1)
+            {
+                output.push_str(&format!("{:>4} | {}\n", index + 1, line));
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+
+    // Reads a file once, spans in the standard library or registry sources can't be read and are skipped
+    fn lines(&mut self, file: &str) -> Option<&Vec<String>> {
+        self.files
+            .entry(file.to_string())
+            .or_insert_with(|| {
+                std::fs::read_to_string(file)
+                    .ok()
+                    .map(|contents| contents.lines().map(String::from).collect())
+            })
+            .as_ref()
+    }
+
+    // The lines around a span
+    fn window(&mut self, span: &Span, window: usize) -> Option<Snippet> {
+        let total = self.lines(&span.file_name)?.len();
+
+        if total == 0 {
+            return None;
+        }
+
+        Some(Snippet {
+            file: span.file_name.clone(),
+            start: span.line_start.saturating_sub(window).max(1),
+            end: (span.line_end + window).min(total),
+        })
+    }
+
+    // The innermost item (function, impl block, ...) that contains `line`
+    fn enclosing_item(&mut self, file: &str, line: usize) -> Option<Snippet> {
+        let (start, end) = enclosing_item(self.lines(file)?, line)?;
+
+        Some(Snippet {
+            file: file.to_string(),
+            start,
+            end,
+        })
+    }
+
+    // Searches the workspace members for the definition of a type or trait called `name`
+    fn definition(&mut self, name: &str) -> Option<Snippet> {
+        for file in Workspace::current().rust_files() {
+            let Some(lines) = self.lines(&file) else {
+                continue;
+            };
+
+            let code = code_only(lines);
+
+            for (start, line) in code.iter().enumerate() {
+                if !defines(line, name) {
+                    continue;
+                }
+
+                let Some(end) = item_end(&code, start) else {
+                    continue;
+                };
+
+                if end - start > MAX_ITEM_LINES {
+                    return None;
+                }
+
+                return Some(Snippet {
+                    file,
+                    start: start + 1,
+                    end: end + 1,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// The lines (1-based, inclusive) of the innermost item (function, impl block, ...) that contains the 1-based `line`
+pub fn enclosing_item(lines: &[String], line: usize) -> Option<(usize, usize)> {
+    let code = code_only(lines);
+
+    for start in (0..line.min(code.len())).rev() {
+        if !is_item_start(&code[start]) {
+            continue;
+        }
+
+        let end = item_end(&code, start)?;
+
+        if end + 1 >= line {
+            if end - start > MAX_ITEM_LINES {
+                return None;
+            }
+
+            return Some((start + 1, end + 1));
+        }
+    }
+
+    None
+}
+
+// Removes visibility and qualifiers from the start of a line
+fn strip_qualifiers(line: &str) -> &str {
+    let mut line = line.trim_start();
+
+    while let Some(qualifier) = QUALIFIERS
+        .iter()
+        .find(|qualifier| line.starts_with(*qualifier))
+    {
+        line = line[qualifier.len()..].trim_start();
+    }
+
+    line
+}
+
+fn is_item_start(line: &str) -> bool {
+    let line = strip_qualifiers(line);
+
+    ITEM_KEYWORDS
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+}
+
+// Whether `line` defines a type or trait called `name`
+fn defines(line: &str, name: &str) -> bool {
+    let line = strip_qualifiers(line);
+
+    DEFINITION_KEYWORDS.iter().any(|keyword| {
+        line.strip_prefix(keyword)
+            .and_then(|rest| rest.strip_prefix(name))
+            .is_some_and(|rest| {
+                !rest
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_alphanumeric() || next == '_')
+            })
+    })
+}
+
+// Finds the 0-based line an item starting at `start` ends on, by matching braces in lines from `code_only`
+fn item_end(code: &[String], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut opened = false;
+
+    for (index, line) in code.iter().enumerate().skip(start) {
+        for character in line.chars() {
+            match character {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth -= 1,
+                // Items like `struct Unit;` or `type Alias = u8;` end before any brace opens
+                ';' if !opened && depth == 0 => return Some(index),
+                _ => {}
+            }
+        }
+
+        if opened && depth <= 0 {
+            return Some(index);
+        }
+    }
+
+    None
+}
+
+// Where the scan is in the source, block comments and strings can span lines
+#[derive(Clone, Copy)]
+enum Scan {
+    Code,
+
+    /// Block comments nest, this is how deep
+    Comment(usize),
+
+    String,
+
+    /// The number of `#` that end the raw string
+    RawString(usize),
+}
+
+// Strips comments and the contents of string and char literals from every line, so braces inside
+// them aren't counted. Lines are kept, so indices still match.
+fn code_only(lines: &[String]) -> Vec<String> {
+    let mut scan = Scan::Code;
+
+    lines
+        .iter()
+        .map(|line| {
+            let characters: Vec<char> = line.chars().collect();
+            let at = |index: usize| characters.get(index).copied();
+            let is_identifier =
+                |index: usize| at(index).is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+            let mut code = String::new();
+            let mut index = 0;
+
+            while let Some(character) = at(index) {
+                match scan {
+                    Scan::Comment(depth) => {
+                        if character == '*' && at(index + 1) == Some('/') {
+                            scan = if depth == 1 {
+                                Scan::Code
+                            } else {
+                                Scan::Comment(depth - 1)
+                            };
+                            index += 1;
+                        } else if character == '/' && at(index + 1) == Some('*') {
+                            scan = Scan::Comment(depth + 1);
+                            index += 1;
+                        }
+                    }
+                    Scan::String => {
+                        if character == '\\' {
+                            index += 1;
+                        } else if character == '"' {
+                            scan = Scan::Code;
+                            code.push('"');
+                        }
+                    }
+                    Scan::RawString(hashes) => {
+                        if character == '"'
+                            && (1..=hashes).all(|offset| at(index + offset) == Some('#'))
+                        {
+                            scan = Scan::Code;
+                            code.push('"');
+                            index += hashes;
+                        }
+                    }
+                    Scan::Code if character == '/' && at(index + 1) == Some('/') => break,
+                    Scan::Code if character == '/' && at(index + 1) == Some('*') => {
+                        scan = Scan::Comment(1);
+                        index += 1;
+                    }
+                    Scan::Code if character == '"' => {
+                        scan = Scan::String;
+                        code.push('"');
+                    }
+                    // `r"..."`, `r#"..."#` and `br"..."`, but not identifiers ending in `r`
+                    Scan::Code
+                        if character == 'r'
+                            && (index == 0
+                                || !is_identifier(index - 1)
+                                || (at(index - 1) == Some('b')
+                                    && (index == 1 || !is_identifier(index - 2)))) =>
+                    {
+                        let hashes = characters[index + 1..]
+                            .iter()
+                            .take_while(|&&c| c == '#')
+                            .count();
+
+                        if at(index + 1 + hashes) == Some('"') {
+                            scan = Scan::RawString(hashes);
+                            code.push('"');
+                            index += 1 + hashes;
+                        } else {
+                            code.push(character);
+                        }
+                    }
+                    // Char literals like `'{'` and `'\''`, anything else is a lifetime
+                    Scan::Code if character == '\'' => {
+                        let end = if at(index + 1) == Some('\\') {
+                            (index + 3..characters.len()).find(|&end| at(end) == Some('\''))
+                        } else {
+                            Some(index + 2).filter(|&end| at(end) == Some('\''))
+                        };
+
+                        match end {
+                            Some(end) => {
+                                code.push_str("''");
+                                index = end;
+                            }
+                            None => code.push(character),
+                        }
+                    }
+                    Scan::Code => code.push(character),
+                }
+
+                index += 1;
+            }
+
+            code
+        })
+        .collect()
+}
+
+// Type-like names (starting with an uppercase letter) that the diagnostic mentions in backticks
+fn referenced_names(diagnostic: &Diagnostic) -> Vec<String> {
+    let mut texts = vec![diagnostic.message.clone()];
+
+    texts.extend(
+        diagnostic
+            .children
+            .iter()
+            .map(|child| child.message.clone()),
+    );
+    texts.extend(
+        diagnostic
+            .all_spans()
+            .into_iter()
+            .filter_map(|span| span.label.clone()),
+    );
+
+    let mut names: Vec<String> = Vec::new();
+
+    for text in texts {
+        // Every other piece is inside backticks
+        for quoted in text.split('`').skip(1).step_by(2) {
+            for name in
+                quoted.split(|character: char| !character.is_alphanumeric() && character != '_')
+            {
+                if name.starts_with(|character: char| character.is_uppercase())
+                    && !STD_NAMES.contains(&name)
+                    && !names.iter().any(|existing| existing == name)
+                {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    names
+}
+
+/// Every `.rs` file under `root`, skipping build output and hidden directories
+pub fn rust_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return files;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if path.is_dir() {
+            if name != "target" && !name.starts_with('.') {
+                files.extend(rust_files(&path));
+            }
+        } else if name.ends_with(".rs") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::diagnostic::{Level, Span};
+
+    use super::*;
+
+    fn lines(source: &str) -> Vec<String> {
+        source.lines().map(String::from).collect()
+    }
+
+    fn diagnostic(message: &str, label: Option<&str>, children: Vec<Diagnostic>) -> Diagnostic {
+        Diagnostic {
+            message: message.to_string(),
+            code: None,
+            level: Level::Error,
+            spans: vec![Span {
+                file_name: String::from("src/main.rs"),
+                byte_start: 0,
+                byte_end: 0,
+                line_start: 1,
+                line_end: 1,
+                column_start: 1,
+                column_end: 2,
+                is_primary: true,
+                text: Vec::new(),
+                label: label.map(String::from),
+                suggested_replacement: None,
+                suggestion_applicability: None,
+                expansion: None,
+            }],
+            children,
+            rendered: None,
+        }
+    }
+
+    #[test]
+    fn finds_the_innermost_enclosing_item() {
+        let source = lines(
+            "struct Point;\n\
+             \n\
+             impl Point {\n\
+             \x20   pub fn new() -> Self {\n\
+             \x20       Point\n\
+             \x20   }\n\
+             \n\
+             \x20   fn origin() {}\n\
+             }\n",
+        );
+
+        assert_eq!(enclosing_item(&source, 5), Some((4, 6)));
+        assert_eq!(enclosing_item(&source, 7), Some((3, 9)));
+        assert_eq!(enclosing_item(&source, 1), Some((1, 1)));
+        assert_eq!(enclosing_item(&source, 2), None);
+    }
+
+    #[test]
+    fn ignores_braces_in_literals_and_comments() {
+        let source = lines(
+            "fn parse(c: char) -> bool {\n\
+             \x20   let open = c == '{' || c == '\\'';\n\
+             \x20   let text = \"}\\\"}\";\n\
+             \x20   let raw = r#\"}\"}\"#;\n\
+             \x20   /* } /* nested } */\n\
+             \x20   } */\n\
+             \x20   fn inner<'a>(x: &'a str) -> &'a str { x } // }\n\
+             \x20   open\n\
+             }\n\
+             \n\
+             fn after() {}\n",
+        );
+
+        assert_eq!(enclosing_item(&source, 8), Some((1, 9)));
+        assert_eq!(enclosing_item(&source, 7), Some((7, 7)));
+    }
+
+    #[test]
+    fn items_in_block_comments_are_not_items() {
+        let source = lines(
+            "fn outer() {\n\
+             \x20   /*\n\
+             \x20   fn commented() {\n\
+             \x20   */\n\
+             \x20   let x = 1;\n\
+             }\n",
+        );
+
+        assert_eq!(enclosing_item(&source, 5), Some((1, 6)));
+    }
+
+    #[test]
+    fn matches_definitions_by_their_whole_name() {
+        assert!(defines("pub(crate) struct Config {", "Config"));
+        assert!(defines("    pub enum Level {", "Level"));
+        assert!(defines("trait Provider: Send {", "Provider"));
+        assert!(defines("type Alias<T> = Vec<T>;", "Alias"));
+        assert!(!defines("struct ConfigBuilder {", "Config"));
+        assert!(!defines("fn Config() {}", "Config"));
+        assert!(!defines("let config = Config::new();", "Config"));
+    }
+
+    #[test]
+    fn collects_type_names_quoted_in_the_diagnostic() {
+        let diagnostic = diagnostic(
+            "mismatched types: expected `Point<Meters>`, found `Option<Point<Feet>>`",
+            Some("expected `Meters` because of this"),
+            vec![diagnostic(
+                "the trait `Convert` is not implemented",
+                None,
+                Vec::new(),
+            )],
+        );
+
+        assert_eq!(
+            referenced_names(&diagnostic),
+            vec!["Point", "Meters", "Feet", "Convert"]
+        );
+    }
+
+    #[test]
+    fn skips_standard_and_lowercase_names() {
+        let diagnostic = diagnostic(
+            "cannot find value `x` in this scope: `Vec<String>` and Result outside backticks",
+            None,
+            Vec::new(),
+        );
+
+        assert!(referenced_names(&diagnostic).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{
+    cli::report::show_explanation,
+    config::Config,
+    diagnostics::diagnostic::Diagnostic,
+    providers::provider::{Message, Provider},
+};
+
+use super::{
+    budget::fit_explanation,
+    cache::ResponseCache,
+    cluster::{cluster, singletons},
+    ledger::{check_budget, show_spending},
+    repair::{request, reserve_request},
+    response::parse_explanation,
+};
+
+/// What the model makes of an error when it's asked to explain rather than fix it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Explanation {
+    /// What the error means, in plain language
+    pub explanation: String,
+
+    /// What in the code most likely causes it
+    pub root_cause: String,
+
+    /// How to fix it, in words
+    pub suggested_fix: String,
+}
+
+/// Asks the model to explain every error in `errors` and prints what it says next to the code.
+///
+/// Related errors are explained together, like they are fixed together. Nothing is written to disk.
+pub async fn explain_errors(config: &Config, provider: &dyn Provider, errors: &[Diagnostic]) {
+    let model = config.model.unwrap();
+    let targets: Vec<&Diagnostic> = errors.iter().collect();
+
+    let clusters = if config.repair.cluster {
+        cluster(&targets)
+    } else {
+        singletons(&targets)
+    };
+
+    let mut cost = 0.0;
+
+    for cluster in clusters {
+        let message = match fit_explanation(config, provider, model, &cluster.diagnostics) {
+            Ok(message) => message,
+            Err(too_large) => {
+                println!(
+                    "⚠️  Skipping {}, it needs {} tokens but {} only has room for {}.",
+                    cluster.headline().bright_red(),
+                    too_large.required.to_string().bright_red(),
+                    model,
+                    too_large.available
+                );
+                continue;
+            }
+        };
+
+        let messages = [Message::user(message)];
+
+        let reservation = match reserve_request(provider, config, &messages, None, check_budget) {
+            Ok(reservation) => reservation,
+            Err(over) => {
+                println!("🛑 Stopped explaining errors, {}.", over);
+                break;
+            }
+        };
+
+        let content = request(
+            provider,
+            config,
+            &messages,
+            None,
+            String::from("💡 Explaining your issue ..."),
+            true,
+            &mut cost,
+        )
+        .await;
+
+        drop(reservation);
+
+        let content = match content {
+            Ok(Some(content)) => content,
+            Ok(None) => continue,
+            Err(err) => {
+                println!("{} Request failed: {}", ">".bright_black(), err);
+                continue;
+            }
+        };
+
+        let parsed = parse_explanation(&content);
+
+        // Explanations aren't verified like fixes, so any that's in the right shape is worth reusing
+        if let Some(cache) = ResponseCache::new(config).filter(|_| parsed.is_ok()) {
+            cache.put(&cache.key(&messages, None), &content);
+        }
+
+        // An explanation is only read, so one that isn't quite in the right shape is still shown as it is
+        let explanation = parsed.unwrap_or_else(|_| Explanation {
+            explanation: content.trim().to_string(),
+            root_cause: String::new(),
+            suggested_fix: String::new(),
+        });
+
+        show_explanation(&cluster.diagnostics, &explanation);
+    }
+
+    println!(
+        "📊 Explained {} {} for {}.",
+        errors.len(),
+        if errors.len() == 1 { "error" } else { "errors" },
+        format!("{:.4}$", cost).bright_cyan()
+    );
+
+    show_spending();
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::prompts::Confirm, config::BudgetConfig, constants::paths::DATA_DIRECTORY};
+
+// How many days of spending are kept for the daily cap
+const DAYS_KEPT: u64 = 31;
+
+// What this session has spent, in dollars
+static SESSION: Mutex<f64> = Mutex::new(0.0);
+
+// Estimated cost of the requests that are on their way but haven't been charged yet
+static RESERVED: Mutex<f64> = Mutex::new(0.0);
+
+// What the user answered when a cap was reached this session, so they're only asked once
+static DECISIONS: Mutex<Vec<(Cap, bool)>> = Mutex::new(Vec::new());
+
+/// What neura has spent on requests to the model, across sessions, stored in `.neura/ledger.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    /// Dollars spent since the ledger was started
+    pub total: f64,
+
+    /// Requests sent to the model
+    pub requests: u64,
+
+    /// Dollars spent per day, keyed on days since the Unix epoch in UTC
+    #[serde(default)]
+    pub days: BTreeMap<u64, f64>,
+}
+
+impl Ledger {
+    fn path() -> PathBuf {
+        Path::new(DATA_DIRECTORY).join("ledger.json")
+    }
+
+    /// Reads the ledger, which is empty when nothing has been spent yet
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(DATA_DIRECTORY)?;
+
+        std::fs::write(Self::path(), serde_json::to_string_pretty(self)?)
+    }
+
+    /// Adds what a request cost to this session, to today and to the total
+    pub fn charge(cost: f64) {
+        *SESSION.lock().unwrap() += cost;
+
+        let today = today();
+
+        let mut ledger = Self::load();
+        ledger.total += cost;
+        ledger.requests += 1;
+        *ledger.days.entry(today).or_default() += cost;
+        ledger
+            .days
+            .retain(|day, _| today.saturating_sub(*day) < DAYS_KEPT);
+
+        // Losing track of the total isn't worth interrupting a fix for
+        let _ = ledger.save();
+    }
+
+    /// What this session has spent so far
+    pub fn session() -> f64 {
+        *SESSION.lock().unwrap()
+    }
+
+    /// What has been spent today, across sessions
+    pub fn today(&self) -> f64 {
+        self.days.get(&today()).copied().unwrap_or_default()
+    }
+}
+
+/// A limit on spending from `[budget]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cap {
+    Request,
+    Session,
+    Day,
+}
+
+impl fmt::Display for Cap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request => write!(f, "per-request"),
+            Self::Session => write!(f, "session"),
+            Self::Day => write!(f, "daily"),
+        }
+    }
+}
+
+/// A request that wasn't sent because it would have gone over a cap in `[budget]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverBudget {
+    cap: Cap,
+    limit: f64,
+}
+
+impl fmt::Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} cap of {:.2}$ would be exceeded, raise it under `[budget]` in neura.toml",
+            self.cap, self.limit
+        )
+    }
+}
+
+impl std::error::Error for OverBudget {}
+
+/// The estimated cost of a request that is on its way, counted against the caps until it's dropped
+/// once the request has been charged
+#[derive(Debug)]
+pub struct Reservation {
+    amount: f64,
+}
+
+impl Reservation {
+    fn new(amount: f64) -> Self {
+        *RESERVED.lock().unwrap() += amount;
+
+        Self { amount }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *RESERVED.lock().unwrap() -= self.amount;
+    }
+}
+
+/// Sets `estimate` aside for a request if it fits within the caps in `[budget]`,
+/// or the user already agreed to go over them this session.
+///
+/// Never asks, so it's safe to call while other requests are running.
+pub fn reserve(budget: &BudgetConfig, estimate: f64) -> Result<Reservation, OverBudget> {
+    match exceeded(budget, estimate, &[]) {
+        Some(over) => Err(over),
+        None => Ok(Reservation::new(estimate)),
+    }
+}
+
+/// Checks a request that's estimated to cost `estimate` against the caps in `[budget]`, and sets the estimate aside.
+///
+/// When it would go over one the user is asked whether to send it anyway. Going over the session or
+/// daily cap is only asked about once per session, the answer holds for every request after it.
+/// Fails when the user says no, or can't be asked because neura isn't running in a terminal.
+/// Nothing else may be drawing to the terminal while this runs.
+pub fn check_budget(budget: &BudgetConfig, estimate: f64) -> Result<Reservation, OverBudget> {
+    // Caps the user agreed to go over for this request
+    let mut approved: Vec<Cap> = Vec::new();
+
+    while let Some(over) = exceeded(budget, estimate, &approved) {
+        // An earlier yes never gets here, so a decision is always a no
+        if DECISIONS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(decided, _)| *decided == over.cap)
+        {
+            return Err(over);
+        }
+
+        let spending = estimate
+            + match over.cap {
+                Cap::Request => 0.0,
+                Cap::Session => Ledger::session() + reserved(),
+                Cap::Day => Ledger::load().today() + reserved(),
+            };
+
+        let proceed = std::io::stdin().is_terminal()
+            && Confirm {
+                message: format!(
+                    "This request could take you to {} of the {} cap of {}, send it anyway?",
+                    format!("{:.4}$", spending).bright_red(),
+                    over.cap,
+                    format!("{:.2}$", over.limit).bright_cyan()
+                )
+                .into(),
+                default: false,
+            }
+            .run()
+            .unwrap_or(false);
+
+        // Every request is measured against the per-request cap on its own
+        if over.cap != Cap::Request {
+            DECISIONS.lock().unwrap().push((over.cap, proceed));
+        }
+
+        if !proceed {
+            return Err(over);
+        }
+
+        approved.push(over.cap);
+    }
+
+    Ok(Reservation::new(estimate))
+}
+
+// The first cap `estimate` would go over that the user hasn't agreed to go over, counting what's
+// set aside for requests on their way
+fn exceeded(budget: &BudgetConfig, estimate: f64, approved: &[Cap]) -> Option<OverBudget> {
+    let reserved = reserved();
+
+    let caps = [
+        (Cap::Request, budget.max_request, estimate),
+        (
+            Cap::Session,
+            budget.max_session,
+            Ledger::session() + reserved + estimate,
+        ),
+        (
+            Cap::Day,
+            budget.max_day,
+            Ledger::load().today() + reserved + estimate,
+        ),
+    ];
+
+    let decisions = DECISIONS.lock().unwrap();
+
+    caps.into_iter()
+        .filter(|(cap, _, _)| !approved.contains(cap) && !decisions.contains(&(*cap, true)))
+        .find_map(|(cap, limit, spending)| {
+            limit
+                .filter(|limit| spending > *limit)
+                .map(|limit| OverBudget { cap, limit })
+        })
+}
+
+fn reserved() -> f64 {
+    *RESERVED.lock().unwrap()
+}
+
+/// Prints what this session and every session so far have cost
+pub fn show_spending() {
+    println!(
+        "💰 Spent {} this session, {} in total.",
+        format!("{:.4}$", Ledger::session()).bright_cyan(),
+        format!("{:.4}$", Ledger::load().total).bright_cyan()
+    );
+}
+
+// Days since the Unix epoch, in UTC
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_on_their_way_count_against_the_session_cap() {
+        let budget = BudgetConfig {
+            max_request: Some(0.5),
+            max_session: Some(1.0),
+            max_day: None,
+        };
+
+        assert_eq!(
+            reserve(&budget, 0.6).unwrap_err(),
+            OverBudget {
+                cap: Cap::Request,
+                limit: 0.5
+            }
+        );
+
+        let first = reserve(&budget, 0.4).unwrap();
+        let second = reserve(&budget, 0.4).unwrap();
+
+        assert_eq!(
+            reserve(&budget, 0.4).unwrap_err(),
+            OverBudget {
+                cap: Cap::Session,
+                limit: 1.0
+            }
+        );
+
+        drop(first);
+        drop(second);
+
+        assert!(reserve(&budget, 0.4).is_ok());
+        assert!(reserved().abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,11 @@
+pub mod budget;
+pub mod cache;
+pub mod changes;
+pub mod cluster;
+pub mod context;
+pub mod explain;
+pub mod ledger;
+pub mod prompt;
+pub mod repair;
+pub mod response;
+pub mod suggestions;
@@ -0,0 +1,96 @@
+use serde_json::json;
+
+use crate::providers::provider::Tool;
+
+/// Version of the prompts and of the answers expected to them, part of every cached answer's key.
+/// Bump it when the way answers are read changes, so answers to the old prompts aren't reused.
+pub const PROMPT_VERSION: u32 = 1;
+
+// Explains how the code in the prompt is laid out
+const EXCERPT: &str = "The code is an excerpt, every line is prefixed with its line number and ' | ', which are not part of the file.";
+
+// Name of the function the model calls with its changes when tools are supported
+const TOOL_NAME: &str = "propose_changes";
+
+// Describes the JSON the model has to respond with
+const RESPONSE_FORMAT: &str = "Respond with a JSON. Use 'changes' for changes needed. Each change should have 'file' (filename), 'diff' (a unified diff of the file with @@ hunk headers and 3 lines of unchanged context around every change), and 'time_estimate_seconds' (time to resolve manually). E.g.: {\"changes\": [{\"file\": \"src/main.rs\", \"diff\": \"@@ -2,3 +2,3 @@\\n use std::io;\\n-fn main() {\\n+pub fn main() {\\n     println!(\\\"hi\\\");\\n\", \"time_estimate_seconds\": 20}]}";
+
+// Describes the JSON the model has to explain an error with
+const EXPLANATION_FORMAT: &str = "Respond with a JSON with three strings: 'explanation' (what the error means, in plain language), 'root_cause' (what in the code most likely causes it) and 'suggested_fix' (how to fix it, in words, with a short code snippet if it helps). E.g.: {\"explanation\": \"...\", \"root_cause\": \"...\", \"suggested_fix\": \"...\"}";
+
+// What the model is told to answer with
+fn format(tools: bool) -> String {
+    if tools {
+        format!("Call `{}` with the changes needed.", TOOL_NAME)
+    } else {
+        RESPONSE_FORMAT.to_string()
+    }
+}
+
+/// The function the model calls with its changes, its arguments have the shape of `Changes`
+pub fn changes_tool() -> Tool {
+    Tool {
+        name: TOOL_NAME.to_string(),
+        description: String::from("Propose changes to files that fix the error"),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "changes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "file": {
+                                "type": "string",
+                                "description": "Path of the file to change, relative to the crate root"
+                            },
+                            "diff": {
+                                "type": "string",
+                                "description": "Unified diff of the file with @@ hunk headers and 3 lines of unchanged context around every change"
+                            },
+                            "time_estimate_seconds": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "description": "How long the change would take to make by hand"
+                            }
+                        },
+                        "required": ["file", "diff", "time_estimate_seconds"]
+                    }
+                }
+            },
+            "required": ["changes"]
+        }),
+    }
+}
+
+/// The first message sent to the model for an error
+pub fn fix(error: &str, context: &str, tools: bool) -> String {
+    format!(
+        "You are an AI debugging copilot: fix the Rust errors below, when there are several they are related and should be fixed together.\nCargo Errors: {}\nRelevant Code:\n{}\n{} {}",
+        error, context, EXCERPT, format(tools)
+    )
+}
+
+/// A follow-up message for when the previous attempt didn't fix the error
+pub fn retry(feedback: &str, error: &str, context: &str, tools: bool) -> String {
+    format!(
+        "Your previous fix did not work: {}\nCargo Errors: {}\nRelevant Code (as it is now):\n{}\n{} Try again with a different approach. {}",
+        feedback, error, context, EXCERPT, format(tools)
+    )
+}
+
+/// Asks the model to turn a response that couldn't be parsed into valid JSON
+pub fn repair_response(response: &str, error: &str, tools: bool) -> String {
+    format!(
+        "This response could not be parsed ({}):\n{}\n\nRewrite it as valid JSON without any other text or code fences. {}",
+        error, response, format(tools)
+    )
+}
+
+/// Asks the model to explain an error rather than fix it
+pub fn explain(error: &str, context: &str) -> String {
+    format!(
+        "You are an AI debugging copilot: explain the Rust errors below to a developer, when there are several they are related and should be explained together. Do not rewrite the code.\nCargo Errors: {}\nRelevant Code:\n{}\n{} {}",
+        error, context, EXCERPT, EXPLANATION_FORMAT
+    )
+}
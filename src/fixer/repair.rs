@@ -0,0 +1,1129 @@
+use colored::Colorize;
+use futures_util::StreamExt;
+use linked_hash_map::LinkedHashMap;
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use crate::{
+    cli::stream::{CancelListener, StreamView},
+    config::{BudgetConfig, Config},
+    diagnostics::{check::introduced_errors, diagnostic::Diagnostic},
+    patch::{git::commit_fix, journal::Journal, rollback::Snapshot},
+    providers::provider::{Completion, CompletionRequest, Message, Provider, Tool},
+    workspace::Workspace,
+};
+
+use super::{
+    budget::{fit_prompt, tokens},
+    cache::ResponseCache,
+    changes::{apply_changes, Applied, Changes},
+    cluster::{cluster, singletons, Cluster},
+    ledger::{check_budget, reserve, show_spending, Ledger, OverBudget, Reservation},
+    prompt,
+    response::parse_changes,
+    suggestions::apply_suggestions,
+};
+
+// Assume a 50$/hr rate
+const RATE_PER_SECOND: f64 = 50.0 / 3600.0;
+
+/// How a single error's repair ended
+enum Outcome {
+    /// The error is gone, `estimated_time` is how long it would have taken by hand
+    Resolved { estimated_time: u64 },
+
+    /// Every attempt failed, or the budget ran out
+    Unresolved,
+
+    /// The user rejected the proposed changes, so there's no point in retrying
+    Declined,
+
+    /// The error doesn't fit in the model's context window, even without any code
+    TooLarge { required: usize, available: usize },
+}
+
+/// Asks the model to fix each error in `targets`, returning the errors that remain afterwards.
+///
+/// `errors` holds every error currently reported by `check`, which is run again to verify each fix,
+/// e.g. `cargo check` or the failing tests.
+///
+/// Errors are grouped by file. Groups don't touch each other's code, so each round one error
+/// of every group is sent to the model at the same time, and all of their fixes are verified
+/// together with a single check.
+pub async fn fix_errors(
+    config: &Config,
+    provider: &dyn Provider,
+    errors: &[Diagnostic],
+    targets: &[Diagnostic],
+    check: &dyn Fn() -> Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let mut errors = errors.to_vec();
+
+    let compiler_fixed = if config.repair.compiler_suggestions {
+        use_compiler_suggestions(config, &mut errors, targets, check)
+    } else {
+        0
+    };
+
+    let remaining_before = remaining(&errors, targets);
+
+    let mut groups: LinkedHashMap<&str, Vec<&Diagnostic>> = LinkedHashMap::new();
+
+    for target in targets {
+        let file = target.file().unwrap_or_default();
+
+        if !Workspace::current().is_editable(file) {
+            println!(
+                "⚠️  Skipping {}, {} isn't part of a workspace member.",
+                target.headline().bright_red(),
+                file.bright_yellow()
+            );
+            continue;
+        }
+
+        groups.entry(file).or_default().push(target);
+    }
+
+    // Related errors within a file are sent together
+    let mut groups: LinkedHashMap<&str, VecDeque<Cluster>> = groups
+        .into_iter()
+        .map(|(file, targets)| {
+            let clusters = if config.repair.cluster {
+                cluster(&targets)
+            } else {
+                singletons(&targets)
+            };
+
+            (file, clusters.into_iter().collect())
+        })
+        .collect();
+
+    // The error each group is working on
+    let mut active: Vec<Repair> = Vec::new();
+
+    loop {
+        for (file, queue) in groups.iter_mut() {
+            if active.iter().any(|repair| repair.cluster.file() == *file) {
+                continue;
+            }
+
+            // An earlier fix may have taken care of the next ones already
+            while let Some(cluster) = queue.pop_front() {
+                if cluster.remaining(&errors).is_empty() {
+                    continue;
+                }
+
+                if cluster.diagnostics.len() > 1 {
+                    cluster.show();
+                }
+
+                active.push(Repair::new(cluster));
+                break;
+            }
+        }
+
+        if active.is_empty() {
+            break;
+        }
+
+        // Going over a cap the user said no to ends the session, not just the repair
+        let finished = match round(config, provider, &mut errors, &mut active, check).await {
+            Ok(finished) => finished,
+            Err(over) => {
+                println!("🛑 Stopped fixing errors, {}.", over);
+                break;
+            }
+        };
+
+        for (repair, outcome) in finished {
+            report(config, &repair, outcome, &errors);
+        }
+    }
+
+    let ai_fixed = remaining_before.saturating_sub(remaining(&errors, targets));
+
+    if compiler_fixed > 0 {
+        println!(
+            "📊 {} fixed by compiler suggestions, {} by the AI.",
+            compiler_fixed.to_string().bright_green(),
+            ai_fixed.to_string().bright_green()
+        );
+    }
+
+    show_spending();
+
+    errors
+}
+
+// Prints how a repair ended
+fn report(config: &Config, repair: &Repair, outcome: Outcome, errors: &[Diagnostic]) {
+    let cluster = &repair.cluster;
+    let attempts = repair.attempts;
+    let cost = repair.cost;
+
+    match outcome {
+        Outcome::Resolved { estimated_time } => {
+            let cost_savings = estimated_time as f64 * RATE_PER_SECOND;
+
+            println!(
+                "✅ Successfully resolved {} in {} after {} {}, saving you {}. {} remain.",
+                cluster.headline().bright_green(),
+                cluster.file().bright_yellow(),
+                attempts,
+                if attempts == 1 { "attempt" } else { "attempts" },
+                format!("{:.2}$", (cost_savings - cost)).bright_cyan(),
+                if errors.is_empty() {
+                    "0".bright_green()
+                } else {
+                    errors.len().to_string().bright_red()
+                }
+            );
+        }
+        Outcome::Unresolved => println!(
+            "❌ Gave up on {} in {} after {} {} ({} spent).",
+            cluster.headline().bright_red(),
+            cluster.file().bright_yellow(),
+            attempts,
+            if attempts == 1 { "attempt" } else { "attempts" },
+            format!("{:.2}$", cost).bright_cyan()
+        ),
+        Outcome::TooLarge {
+            required,
+            available,
+        } => println!(
+            "⚠️  Skipping {}, it needs {} tokens but {} only has room for {}.",
+            cluster.headline().bright_red(),
+            required.to_string().bright_red(),
+            config.model.unwrap(),
+            available
+        ),
+        Outcome::Declined => println!(
+            "{} Leaving {} as it is.",
+            ">".bright_black(),
+            cluster.headline().bright_red()
+        ),
+    }
+}
+
+// How many of `targets` are still among `errors`
+fn remaining(errors: &[Diagnostic], targets: &[Diagnostic]) -> usize {
+    targets
+        .iter()
+        .filter(|target| errors.iter().any(|error| error.is_same(target)))
+        .count()
+}
+
+// Puts the files of a snapshot back, reporting the ones that couldn't be so the rest of the session can go on
+fn restore(snapshot: &Snapshot) {
+    let Err(failed) = snapshot.restore() else {
+        return;
+    };
+
+    for (file, error) in failed {
+        println!(
+            "⚠️  Couldn't roll back {}, it's left with the changes: {}",
+            file.bright_yellow(),
+            error
+        );
+    }
+}
+
+// Prints what was reverted and which errors caused it
+fn report_rollback(
+    snapshot: &Snapshot,
+    before: &[Diagnostic],
+    after: &[Diagnostic],
+    introduced: &[Diagnostic],
+) {
+    println!(
+        "↩️  Rolled back changes to {}, the fix left {} {} behind ({} before).",
+        snapshot
+            .files()
+            .iter()
+            .map(|file| file.bright_yellow().to_string())
+            .collect::<Vec<String>>()
+            .join(", "),
+        after.len().to_string().bright_red(),
+        if after.len() == 1 { "error" } else { "errors" },
+        before.len()
+    );
+
+    for error in introduced {
+        println!(
+            "{} Introduced in {}: {}",
+            ">".bright_black(),
+            error.file().unwrap_or_default().bright_yellow(),
+            error.headline()
+        );
+    }
+}
+
+// Lets rustc fix what it can before anything is sent to the model, returning how many targets it fixed
+fn use_compiler_suggestions(
+    config: &Config,
+    errors: &mut Vec<Diagnostic>,
+    targets: &[Diagnostic],
+    check: &dyn Fn() -> Vec<Diagnostic>,
+) -> usize {
+    let (snapshot, applied) = apply_suggestions(config, targets);
+
+    if snapshot.is_empty() {
+        return 0;
+    }
+
+    let spinner = indicatif::ProgressBar::new_spinner();
+    spinner.set_message("💻 Checking the compiler suggestions ...");
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let new_errors = check();
+    spinner.finish_and_clear();
+    let introduced = introduced_errors(errors, &new_errors);
+
+    if config
+        .rollback
+        .should_rollback(errors.len(), new_errors.len(), introduced.len())
+    {
+        restore(&snapshot);
+        report_rollback(&snapshot, errors, &new_errors, &introduced);
+
+        return 0;
+    }
+
+    let remaining_before = remaining(errors, targets);
+    *errors = new_errors;
+    let fixed = remaining_before.saturating_sub(remaining(errors, targets));
+
+    println!(
+        "🔧 Applied compiler suggestions for {} {}, resolving {}.",
+        applied.len(),
+        if applied.len() == 1 {
+            "error"
+        } else {
+            "errors"
+        },
+        fixed.to_string().bright_green()
+    );
+
+    keep(
+        config,
+        &snapshot,
+        "neura: apply compiler suggestions",
+        &applied.iter().collect::<Vec<&Diagnostic>>(),
+        "rustc",
+    );
+
+    fixed
+}
+
+// Records a fix that is there to stay in the journal, so it can be undone later, and commits it when git is enabled
+fn keep(
+    config: &Config,
+    snapshot: &Snapshot,
+    subject: &str,
+    diagnostics: &[&Diagnostic],
+    model: &str,
+) {
+    Journal::record(snapshot, diagnostics, model);
+
+    commit_fix(&config.git, snapshot, subject, diagnostics);
+}
+
+/// The fix loop for a cluster of related errors: ask, apply, check, and feed the result back until they're fixed
+struct Repair<'t> {
+    cluster: Cluster<'t>,
+
+    /// The conversation so far
+    messages: Vec<Message>,
+
+    /// Why the last attempt failed, sent along with the next one
+    feedback: Option<String>,
+
+    cost: f64,
+    attempts: usize,
+
+    /// The cache key of the prompt behind the last attempt and the answer it got,
+    /// only stored in the cache once the attempt's fix is kept
+    answer: Option<(String, String)>,
+
+    /// What's set aside in the budget for the next request
+    reservation: Option<Reservation>,
+}
+
+/// What a single request to the model came back with
+enum Step {
+    /// Changes to apply and verify
+    Changes(Changes),
+
+    /// Nothing usable, `feedback` says why
+    Retry,
+
+    /// The repair is over
+    Done(Outcome),
+}
+
+impl<'t> Repair<'t> {
+    fn new(cluster: Cluster<'t>) -> Self {
+        Self {
+            cluster,
+            messages: Vec::new(),
+            feedback: None,
+            cost: 0.0,
+            attempts: 0,
+            answer: None,
+            reservation: None,
+        }
+    }
+
+    // Stores the answer behind a fix that was kept, so the same prompt gets it again without asking
+    fn remember(&mut self, config: &Config) {
+        if let Some(((key, content), cache)) = self.answer.take().zip(ResponseCache::new(config)) {
+            cache.put(&key, &content);
+        }
+    }
+
+    // Forgets the answer behind an attempt that didn't work out, so it isn't replayed
+    fn forget(&mut self, config: &Config) {
+        if let Some(((key, _), cache)) = self.answer.take().zip(ResponseCache::new(config)) {
+            cache.remove(&key);
+        }
+    }
+
+    // Builds the prompt for the next attempt, returning how the repair ended if there's no next attempt
+    fn prepare(
+        &mut self,
+        config: &Config,
+        provider: &dyn Provider,
+        errors: &[Diagnostic],
+    ) -> Option<Outcome> {
+        let model = config.model.unwrap();
+
+        if self.attempts >= config.repair.max_attempts.max(1) {
+            return Some(Outcome::Unresolved);
+        }
+
+        if let Some(max_cost) = config.repair.max_cost {
+            if self.cost >= max_cost {
+                println!(
+                    "{} Stopping, {} has cost {} of the {} allowed.",
+                    ">".bright_black(),
+                    self.cluster.headline().bright_red(),
+                    format!("{:.2}$", self.cost).bright_cyan(),
+                    format!("{:.2}$", max_cost).bright_cyan()
+                );
+
+                return Some(Outcome::Unresolved);
+            }
+        }
+
+        // The errors' messages may have changed since the last attempt, e.g. their line numbers
+        let current = self.cluster.remaining(errors);
+
+        // So may the code around it, so the prompt is always built again
+        let message = match fit_prompt(
+            config,
+            provider,
+            model,
+            &mut self.messages,
+            &current,
+            self.feedback.as_deref(),
+        ) {
+            Ok(message) => message,
+            Err(too_large) => {
+                return Some(Outcome::TooLarge {
+                    required: too_large.required,
+                    available: too_large.available,
+                })
+            }
+        };
+
+        self.attempts += 1;
+        self.messages.push(Message::user(message));
+
+        None
+    }
+
+    // Checks the prepared prompt against the budget, asking the user about going over a cap
+    fn reserve(&mut self, config: &Config, provider: &dyn Provider) -> Result<(), OverBudget> {
+        self.reservation = reserve_request(
+            provider,
+            config,
+            &self.messages,
+            provider
+                .supports_tools()
+                .then(prompt::changes_tool)
+                .as_ref(),
+            check_budget,
+        )?;
+
+        Ok(())
+    }
+
+    // Asks the model for the prepared attempt, streaming the response to the terminal if `stream` is set
+    async fn ask(&mut self, config: &Config, provider: &dyn Provider, stream: bool) -> Step {
+        let max_attempts = config.repair.max_attempts.max(1);
+
+        let status = if self.attempts == 1 {
+            String::from("🐛 Debugging your issue ...")
+        } else {
+            format!(
+                "🐛 Debugging your issue (attempt {}/{}) ...",
+                self.attempts, max_attempts
+            )
+        };
+
+        let tool = provider.supports_tools().then(prompt::changes_tool);
+        let key = ResponseCache::new(config).map(|cache| cache.key(&self.messages, tool.as_ref()));
+
+        let response = request(
+            provider,
+            config,
+            &self.messages,
+            tool,
+            status,
+            stream,
+            &mut self.cost,
+        )
+        .await;
+
+        // The request has been charged by now
+        self.reservation = None;
+
+        // A failed request shouldn't end the session, the error is reported and skipped
+        let content = match response {
+            Ok(Some(content)) => content,
+            Ok(None) => return Step::Done(Outcome::Declined),
+            Err(err) => {
+                println!("{} Request failed: {}", ">".bright_black(), err);
+                return Step::Done(Outcome::Unresolved);
+            }
+        };
+
+        let mut parsed = parse_changes(&content);
+        let mut content = content;
+
+        // Most malformed responses are the right answer in the wrong format, so the model gets one chance to fix it
+        if let Some(err) = parsed
+            .as_ref()
+            .err()
+            .filter(|_| config.repair.repair_responses)
+        {
+            println!(
+                "{} Could not parse the response ({}), asking for a corrected one",
+                ">".bright_black(),
+                err
+            );
+
+            // Only the broken response is sent, it's all the model needs and it always fits
+            let repair = vec![Message::user(prompt::repair_response(
+                &content,
+                &err.to_string(),
+                provider.supports_tools(),
+            ))];
+
+            // Other requests may be running, so instead of asking about a cap the correction is skipped
+            // and the next attempt is checked before it's sent
+            match reserve_request(
+                provider,
+                config,
+                &repair,
+                provider
+                    .supports_tools()
+                    .then(prompt::changes_tool)
+                    .as_ref(),
+                reserve,
+            ) {
+                Ok(reservation) => {
+                    let repaired = request(
+                        provider,
+                        config,
+                        &repair,
+                        provider.supports_tools().then(prompt::changes_tool),
+                        String::from("🩹 Repairing the response ..."),
+                        stream,
+                        &mut self.cost,
+                    )
+                    .await;
+
+                    drop(reservation);
+
+                    match repaired {
+                        Ok(Some(repaired)) => {
+                            parsed = parse_changes(&repaired);
+                            content = repaired;
+                        }
+                        Ok(None) => return Step::Done(Outcome::Declined),
+                        Err(err) => println!("{} Request failed: {}", ">".bright_black(), err),
+                    }
+                }
+                Err(over) => println!(
+                    "{} Not asking for a corrected one, {}",
+                    ">".bright_black(),
+                    over
+                ),
+            }
+        }
+
+        // The usable answer is what gets stored for the prompt, even when it took a repair to get it
+        self.answer = key.map(|key| (key, content.clone()));
+
+        // An unusable answer would only be reused to fail again
+        if parsed.is_err() {
+            self.forget(config);
+        }
+
+        self.messages.push(Message::assistant(content));
+
+        match parsed {
+            Ok(changes) => Step::Changes(changes),
+            Err(err) => {
+                println!("{} Unusable response: {}", ">".bright_black(), err);
+                self.feedback = Some(format!("your response could not be parsed ({})", err));
+                Step::Retry
+            }
+        }
+    }
+}
+
+// Runs one attempt of every active repair: asks for all of them concurrently, applies their changes,
+// and verifies them with a single check. Returns the repairs that are over, along with how they ended.
+async fn round<'t>(
+    config: &Config,
+    provider: &dyn Provider,
+    errors: &mut Vec<Diagnostic>,
+    active: &mut Vec<Repair<'t>>,
+    check: &dyn Fn() -> Vec<Diagnostic>,
+) -> Result<Vec<(Repair<'t>, Outcome)>, OverBudget> {
+    let concurrency = config.repair.concurrency.max(1);
+
+    // Every request is checked against the budget one at a time before any is sent, so each check
+    // counts the ones before it and nothing draws over the question when a cap would be reached
+    let mut prepared: Vec<Option<Outcome>> = Vec::new();
+
+    for repair in active.iter_mut() {
+        let outcome = repair.prepare(config, provider, errors);
+
+        if outcome.is_none() {
+            repair.reserve(config, provider)?;
+        }
+
+        prepared.push(outcome);
+    }
+
+    let asking = prepared.iter().filter(|outcome| outcome.is_none()).count();
+
+    // Responses can only be streamed to the terminal one at a time
+    let stream = concurrency == 1 || asking <= 1;
+
+    let spinner = (!stream).then(|| {
+        let spinner = indicatif::ProgressBar::new_spinner();
+        spinner.set_message(format!("🐛 Debugging {} errors in parallel ...", asking));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        spinner
+    });
+
+    // `buffered` keeps the steps in the same order as the repairs
+    let steps: Vec<Step> = futures_util::stream::iter(active.iter_mut().zip(prepared).map(
+        |(repair, outcome)| async move {
+            match outcome {
+                Some(outcome) => Step::Done(outcome),
+                None => repair.ask(config, provider, stream).await,
+            }
+        },
+    ))
+    .buffered(concurrency)
+    .collect()
+    .await;
+
+    if let Some(spinner) = spinner {
+        spinner.finish_and_clear();
+    }
+
+    let mut outcomes: Vec<Option<Outcome>> = Vec::new();
+    let mut pending: Vec<(usize, Applied)> = Vec::new();
+
+    // Changes are reviewed and written one repair at a time
+    for (index, step) in steps.into_iter().enumerate() {
+        outcomes.push(None);
+
+        let changes = match step {
+            Step::Changes(changes) => changes,
+            Step::Retry => continue,
+            Step::Done(outcome) => {
+                outcomes[index] = Some(outcome);
+                continue;
+            }
+        };
+
+        let applied = apply_changes(config, &changes.changes);
+
+        if applied.snapshot.is_empty() {
+            active[index].forget(config);
+        }
+
+        if applied.rejected && applied.snapshot.is_empty() {
+            outcomes[index] = Some(Outcome::Declined);
+            continue;
+        }
+
+        if applied.snapshot.is_empty() {
+            active[index].feedback = Some(if applied.problems.is_empty() {
+                String::from("you did not propose any changes")
+            } else {
+                format!(
+                    "none of the changes could be applied ({})",
+                    applied.problems.join("; ")
+                )
+            });
+            continue;
+        }
+
+        pending.push((index, applied));
+    }
+
+    if !pending.is_empty() {
+        verify(config, errors, active, &mut outcomes, pending, check);
+    }
+
+    // Finished repairs are taken out, the rest carry on in the next round
+    let mut finished = Vec::new();
+
+    for (repair, outcome) in std::mem::take(active).into_iter().zip(outcomes) {
+        match outcome {
+            Some(outcome) => finished.push((repair, outcome)),
+            None => active.push(repair),
+        }
+    }
+
+    Ok(finished)
+}
+
+// Checks the changes written in a round, rolling back the ones that made things worse
+fn verify(
+    config: &Config,
+    errors: &mut Vec<Diagnostic>,
+    active: &mut [Repair],
+    outcomes: &mut [Option<Outcome>],
+    pending: Vec<(usize, Applied)>,
+    check: &dyn Fn() -> Vec<Diagnostic>,
+) {
+    let mut new_errors = check();
+    let introduced = introduced_errors(errors, &new_errors);
+
+    let mut reverted: Vec<usize> = Vec::new();
+
+    if config
+        .rollback
+        .should_rollback(errors.len(), new_errors.len(), introduced.len())
+    {
+        reverted = blame(&pending, &introduced);
+
+        // Later changes were written on top of earlier ones, so they are restored first
+        for (index, applied) in pending.iter().rev() {
+            if !reverted.contains(index) {
+                continue;
+            }
+
+            restore(&applied.snapshot);
+
+            report_rollback(&applied.snapshot, errors, &new_errors, &introduced);
+
+            active[*index].forget(config);
+            active[*index].feedback = Some(format!(
+                "it was reverted because it introduced these errors:\n{}",
+                introduced
+                    .iter()
+                    .map(|error| error.rendered())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+        }
+
+        // What's left of the round has to be checked again without the reverted changes
+        new_errors = if reverted.len() < pending.len() {
+            check()
+        } else {
+            errors.clone()
+        };
+    }
+
+    // Attempts that didn't fix a single error of their cluster are undone before the next try
+    let unproductive: Vec<usize> = pending
+        .iter()
+        .map(|(index, _)| *index)
+        .filter(|index| {
+            let cluster = &active[*index].cluster;
+
+            !reverted.contains(index)
+                && cluster.remaining(&new_errors).len() == cluster.diagnostics.len()
+        })
+        .collect();
+
+    let mut undone = unproductive.clone();
+    entangled(&pending, &mut undone);
+    undone.retain(|index| !reverted.contains(index));
+
+    for (index, applied) in pending.iter().rev() {
+        if !undone.contains(index) {
+            continue;
+        }
+
+        restore(&applied.snapshot);
+
+        let files = applied
+            .snapshot
+            .files()
+            .iter()
+            .map(|file| file.bright_yellow().to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        if unproductive.contains(index) {
+            println!(
+                "↩️  Rolled back changes to {}, they didn't fix {}.",
+                files,
+                active[*index].cluster.headline().bright_red()
+            );
+        } else {
+            println!(
+                "↩️  Rolled back changes to {} for {}, a change that was rolled back touched the same files.",
+                files,
+                active[*index].cluster.headline().bright_red()
+            );
+        }
+    }
+
+    if !undone.is_empty() {
+        new_errors = if reverted.len() + undone.len() < pending.len() {
+            check()
+        } else {
+            errors.clone()
+        };
+    }
+
+    *errors = new_errors;
+
+    for (index, applied) in pending {
+        let repair = &mut active[index];
+
+        if undone.contains(&index) {
+            repair.forget(config);
+
+            if !unproductive.contains(&index) {
+                repair.feedback = Some(String::from(
+                    "it was reverted along with another change to the same files, which didn't fix its errors",
+                ));
+            } else if applied.rejected {
+                outcomes[index] = Some(Outcome::Declined);
+            } else {
+                repair.feedback = Some(String::from(
+                    "it was reverted because the errors were still there after the changes",
+                ));
+            }
+
+            continue;
+        }
+
+        if reverted.contains(&index) {
+            continue;
+        }
+
+        let remaining = repair.cluster.remaining(errors);
+
+        keep(
+            config,
+            &applied.snapshot,
+            &format!(
+                "neura: {} {}",
+                if remaining.is_empty() {
+                    "fix"
+                } else {
+                    "partly fix"
+                },
+                repair.cluster.headline()
+            ),
+            &repair.cluster.diagnostics,
+            &config.model_name(),
+        );
+
+        // Only an answer that fixed everything it was asked to is worth replaying
+        if remaining.is_empty() {
+            repair.remember(config);
+        } else {
+            repair.forget(config);
+        }
+
+        if remaining.is_empty() {
+            outcomes[index] = Some(Outcome::Resolved {
+                estimated_time: applied.estimated_time,
+            });
+        } else if applied.rejected {
+            outcomes[index] = Some(Outcome::Declined);
+        } else {
+            repair.feedback = Some(format!(
+                "the changes were applied, but these errors are still there: {}",
+                remaining
+                    .iter()
+                    .map(|error| error.headline())
+                    .collect::<Vec<String>>()
+                    .join("; ")
+            ));
+        }
+    }
+}
+
+// Which of the round's changes to roll back: those that touched a file a new error showed up in,
+// or all of them when it's not clear which one is to blame
+fn blame(pending: &[(usize, Applied)], introduced: &[Diagnostic]) -> Vec<usize> {
+    let touches = |applied: &Applied, files: &[&String]| {
+        applied
+            .snapshot
+            .files()
+            .iter()
+            .any(|file| files.contains(file))
+    };
+
+    let introduced_files: Vec<String> = introduced
+        .iter()
+        .filter_map(|error| error.file().map(String::from))
+        .collect();
+    let introduced_files: Vec<&String> = introduced_files.iter().collect();
+
+    let mut blamed: Vec<usize> = pending
+        .iter()
+        .filter(|(_, applied)| touches(applied, &introduced_files))
+        .map(|(index, _)| *index)
+        .collect();
+
+    if blamed.is_empty() {
+        return pending.iter().map(|(index, _)| *index).collect();
+    }
+
+    entangled(pending, &mut blamed);
+
+    blamed
+}
+
+// Adds to `reverted` every change of the round that touched a file one of them touched,
+// since restoring a file also undoes every other change made to it in the round
+fn entangled(pending: &[(usize, Applied)], reverted: &mut Vec<usize>) {
+    loop {
+        let reverted_files: Vec<&String> = pending
+            .iter()
+            .filter(|(index, _)| reverted.contains(index))
+            .flat_map(|(_, applied)| applied.snapshot.files())
+            .collect();
+
+        let more: Vec<usize> = pending
+            .iter()
+            .filter(|(index, applied)| {
+                !reverted.contains(index)
+                    && applied
+                        .snapshot
+                        .files()
+                        .iter()
+                        .any(|file| reverted_files.contains(file))
+            })
+            .map(|(index, _)| *index)
+            .collect();
+
+        if more.is_empty() {
+            return;
+        }
+
+        reverted.extend(more);
+    }
+}
+
+/// Checks a request for `messages` against the caps in `[budget]` with `check`, setting aside what it could cost:
+/// the prompt and the longest answer the model may give. Answers found in the response cache are free.
+pub fn reserve_request(
+    provider: &dyn Provider,
+    config: &Config,
+    messages: &[Message],
+    tool: Option<&Tool>,
+    check: fn(&BudgetConfig, f64) -> Result<Reservation, OverBudget>,
+) -> Result<Option<Reservation>, OverBudget> {
+    if ResponseCache::new(config)
+        .is_some_and(|cache| cache.get(&cache.key(messages, tool)).is_some())
+    {
+        return Ok(None);
+    }
+
+    let model = config.model.unwrap();
+    let estimate = config
+        .price()
+        .cost(tokens(provider, messages), model.max_output_tokens());
+
+    check(&config.budget, estimate).map(Some)
+}
+
+/// Sends a conversation to the model, adding what it cost to `cost` and to the ledger.
+///
+/// When `tool` is set the model is made to answer by calling it. When `stream` is set
+/// the completion is shown as it comes in and can be cancelled, which returns `None`.
+/// An answer to the same conversation found in the response cache is returned without asking the model.
+/// Answers aren't stored here, only the caller knows whether one turned out to be worth reusing.
+/// Nor is the budget checked, that's up to the caller with [`reserve_request`] before it's sent.
+pub async fn request(
+    provider: &dyn Provider,
+    config: &Config,
+    messages: &[Message],
+    tool: Option<Tool>,
+    status: String,
+    stream: bool,
+    cost: &mut f64,
+) -> miette::Result<Option<String>> {
+    let model = config.model.unwrap();
+    let price = config.price();
+
+    if let Some(content) =
+        ResponseCache::new(config).and_then(|cache| cache.get(&cache.key(messages, tool.as_ref())))
+    {
+        println!(
+            "♻️  Reusing an earlier answer to the same prompt, nothing was sent to the model."
+        );
+        return Ok(Some(content));
+    }
+
+    // Prompt token count
+    let prompt_token_count = tokens(provider, messages);
+    println!(
+        "📝 Prompt token count: {} of {}",
+        prompt_token_count,
+        model.context_window() - model.max_output_tokens()
+    );
+
+    let request = CompletionRequest {
+        messages: messages.to_vec(),
+        max_tokens: model.max_output_tokens() as u64,
+        temperature: 0.2,
+        tool,
+    };
+
+    // The provider's own token counts are exact, counting them ourselves is only an estimate
+    let mut charge = |completion: &Completion| {
+        let (prompt_tokens, completion_tokens) = match completion.usage {
+            Some(usage) => (
+                usage.prompt_tokens as usize,
+                usage.completion_tokens as usize,
+            ),
+            None => (
+                prompt_token_count,
+                provider.count_tokens(&completion.content),
+            ),
+        };
+
+        let spent = price.cost(prompt_tokens, completion_tokens);
+
+        *cost += spent;
+        Ledger::charge(spent);
+    };
+
+    if !stream {
+        let completion = provider.complete(&request).await?;
+
+        charge(&completion);
+
+        return Ok(Some(completion.content));
+    }
+
+    let view = Mutex::new(StreamView::new(status, price, prompt_token_count));
+    let (listener, mut cancelled) = CancelListener::start();
+
+    // Whatever streamed in is paid for, even if the request is cancelled
+    let mut received = String::new();
+
+    let completion = {
+        let mut on_token = |chunk: &str| {
+            received.push_str(chunk);
+            view.lock()
+                .unwrap()
+                .push(chunk, provider.count_tokens(chunk));
+        };
+
+        let stream = provider.stream(&request, &mut on_token);
+        tokio::pin!(stream);
+
+        let mut ticker = tokio::time::interval(Duration::from_millis(100));
+
+        loop {
+            tokio::select! {
+                completion = &mut stream => break Some(completion),
+                Ok(()) = &mut cancelled => break None,
+                _ = ticker.tick() => view.lock().unwrap().tick(),
+            }
+        }
+    };
+
+    view.lock().unwrap().finish();
+    listener.stop();
+
+    let Some(completion) = completion else {
+        // A cancelled stream never reports its usage
+        charge(&Completion {
+            content: received,
+            usage: None,
+        });
+
+        println!("{} Cancelled", ">".bright_black());
+
+        return Ok(None);
+    };
+
+    let completion = completion?;
+
+    charge(&completion);
+
+    Ok(Some(completion.content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A change of the round that touched `files`, which don't need to exist
+    fn applied(files: &[&str]) -> Applied {
+        let mut snapshot = Snapshot::new();
+
+        for file in files {
+            snapshot.record(file);
+        }
+
+        Applied {
+            snapshot,
+            ..Applied::default()
+        }
+    }
+
+    #[test]
+    fn undoing_a_change_undoes_the_others_to_the_same_files() {
+        let pending = vec![
+            (0, applied(&["missing/a.rs", "missing/b.rs"])),
+            (1, applied(&["missing/b.rs"])),
+            (2, applied(&["missing/c.rs"])),
+        ];
+
+        let mut reverted = vec![0];
+        entangled(&pending, &mut reverted);
+        assert_eq!(reverted, vec![0, 1]);
+
+        // Only the later change is unproductive, but restoring `b.rs` takes the earlier one with it
+        let mut reverted = vec![1];
+        entangled(&pending, &mut reverted);
+        assert_eq!(reverted, vec![1, 0]);
+    }
+
+    #[test]
+    fn follows_shared_files_transitively() {
+        let pending = vec![
+            (0, applied(&["missing/a.rs"])),
+            (1, applied(&["missing/a.rs", "missing/b.rs"])),
+            (2, applied(&["missing/b.rs"])),
+            (3, applied(&["missing/c.rs"])),
+        ];
+
+        let mut reverted = vec![2];
+        entangled(&pending, &mut reverted);
+        reverted.sort();
+
+        assert_eq!(reverted, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,418 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use super::{changes::Changes, explain::Explanation};
+
+/// Why a model's response couldn't be turned into changes
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseError {
+    /// There is nothing that looks like JSON in the response
+    NoJson,
+
+    /// The JSON is malformed, the position is relative to where the JSON starts
+    Syntax {
+        line: usize,
+        column: usize,
+        reason: String,
+    },
+
+    /// The JSON is well-formed but doesn't have the expected shape, `path` points at the offending value
+    Schema { path: String, reason: String },
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoJson => write!(f, "the response does not contain a JSON object"),
+            Self::Syntax {
+                line,
+                column,
+                reason,
+            } => write!(
+                f,
+                "invalid JSON at line {} column {}: {}",
+                line, column, reason
+            ),
+            Self::Schema { path, reason } => write!(f, "`{}` {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+/// Parses the changes out of a model's response.
+///
+/// Models like to wrap JSON in Markdown fences or explain themselves around it,
+/// so the JSON is looked for in the whole response, then in fenced blocks, then in the first `{...}`.
+pub fn parse_changes(content: &str) -> Result<Changes, ResponseError> {
+    parse(content, validate)
+}
+
+/// Parses an explanation out of a model's response, looking for the JSON the same way `parse_changes` does
+pub fn parse_explanation(content: &str) -> Result<Explanation, ResponseError> {
+    parse(content, |value| {
+        let Value::Object(object) = &value else {
+            return Err(schema("$", expected("an object", &value)));
+        };
+
+        for field in ["explanation", "root_cause", "suggested_fix"] {
+            match object.get(field) {
+                Some(Value::String(_)) => {}
+                Some(other) => return Err(schema(field, expected("a string", other))),
+                None => return Err(schema(field, "is missing")),
+            }
+        }
+
+        serde_json::from_value(value).map_err(|err| schema("$", err.to_string()))
+    })
+}
+
+// Tries each candidate in turn, handing the first one that is valid JSON to `validate`
+fn parse<T>(
+    content: &str,
+    validate: impl Fn(Value) -> Result<T, ResponseError>,
+) -> Result<T, ResponseError> {
+    let candidates = candidates(content);
+
+    let mut syntax_error = None;
+
+    for candidate in &candidates {
+        match serde_json::from_str::<Value>(candidate) {
+            Ok(value) => return validate(value),
+            Err(err) => {
+                // The first candidate is the most likely to be what the model meant, so its error is reported
+                syntax_error.get_or_insert(ResponseError::Syntax {
+                    line: err.line(),
+                    column: err.column(),
+                    // serde puts the position at the end of its message, it's already shown separately
+                    reason: err
+                        .to_string()
+                        .split(" at line ")
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                });
+            }
+        }
+    }
+
+    Err(syntax_error.unwrap_or(ResponseError::NoJson))
+}
+
+// Pieces of the response that may be the JSON, most likely first
+fn candidates(content: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+
+    let trimmed = content.trim();
+
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        candidates.push(trimmed.to_string());
+    }
+
+    candidates.extend(fenced_blocks(content));
+
+    if let Some(object) = first_object(content) {
+        if !candidates.contains(&object) {
+            candidates.push(object);
+        }
+    }
+
+    candidates
+}
+
+// The contents of every ``` fenced block, whatever language it is tagged with
+fn fenced_blocks(content: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block.join("\n").trim().to_string()),
+                None => current = Some(Vec::new()),
+            }
+        } else if let Some(block) = current.as_mut() {
+            block.push(line);
+        }
+    }
+
+    // An unterminated fence usually means the response was cut off, it's still worth a try
+    if let Some(block) = current {
+        blocks.push(block.join("\n").trim().to_string());
+    }
+
+    blocks
+}
+
+// The first balanced `{...}` in the response, skipping braces inside strings
+fn first_object(content: &str) -> Option<String> {
+    let start = content.find('{')?;
+
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, character) in content[start..].char_indices() {
+        if in_string {
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match character {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(content[start..=start + index].to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Unbalanced, so let serde report where it goes wrong
+    Some(content[start..].to_string())
+}
+
+// Checks the JSON has the shape of `Changes`, so problems can be pointed at precisely
+fn validate(value: Value) -> Result<Changes, ResponseError> {
+    let changes = match value {
+        // A bare list of changes is unambiguous enough to accept
+        Value::Array(changes) => changes,
+        Value::Object(mut object) => match object.remove("changes") {
+            Some(Value::Array(changes)) => changes,
+            Some(other) => return Err(schema("changes", expected("an array", &other))),
+            None => return Err(schema("changes", "is missing")),
+        },
+        other => return Err(schema("$", expected("an object", &other))),
+    };
+
+    for (index, change) in changes.iter().enumerate() {
+        let path = format!("changes[{}]", index);
+
+        let Value::Object(change) = change else {
+            return Err(schema(&path, expected("an object", change)));
+        };
+
+        for field in ["file", "diff"] {
+            match change.get(field) {
+                Some(Value::String(text)) if text.trim().is_empty() => {
+                    return Err(schema(&format!("{}.{}", path, field), "is empty"))
+                }
+                Some(Value::String(_)) => {}
+                Some(other) => {
+                    return Err(schema(
+                        &format!("{}.{}", path, field),
+                        expected("a string", other),
+                    ))
+                }
+                None => return Err(schema(&format!("{}.{}", path, field), "is missing")),
+            }
+        }
+
+        if let Some(estimate) = change.get("time_estimate_seconds") {
+            if !estimate.is_u64() {
+                return Err(schema(
+                    &format!("{}.time_estimate_seconds", path),
+                    expected("a whole number of seconds", estimate),
+                ));
+            }
+        }
+    }
+
+    serde_json::from_value(Value::Array(changes))
+        .map(|changes| Changes { changes })
+        .map_err(|err| schema("changes", err.to_string()))
+}
+
+fn schema(path: &str, reason: impl Into<String>) -> ResponseError {
+    ResponseError::Schema {
+        path: path.to_string(),
+        reason: reason.into(),
+    }
+}
+
+fn expected(what: &str, found: &Value) -> String {
+    let found = match found {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    };
+
+    format!("should be {}, found {}", what, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGES: &str = r#"{"changes": [{"file": "src/main.rs", "diff": "@@ @@\n-a\n+b\n", "time_estimate_seconds": 30}]}"#;
+
+    fn error(content: &str) -> ResponseError {
+        parse_changes(content).err().unwrap()
+    }
+
+    #[test]
+    fn parses_bare_json() {
+        let changes = parse_changes(CHANGES).unwrap();
+
+        assert_eq!(changes.changes.len(), 1);
+        assert_eq!(changes.changes[0].file, "src/main.rs");
+        assert_eq!(changes.changes[0].diff, "@@ @@\n-a\n+b\n");
+        assert_eq!(changes.changes[0].time_estimate_seconds, 30);
+    }
+
+    #[test]
+    fn parses_json_in_a_fenced_block() {
+        let content = format!(
+            "Here's the fix:\n\n```json\n{}\n```\n\nThis replaces `a` with `b`.",
+            CHANGES
+        );
+
+        assert_eq!(parse_changes(&content).unwrap().changes.len(), 1);
+    }
+
+    #[test]
+    fn parses_json_in_an_unterminated_fenced_block() {
+        let content = format!("```\n{}\n", CHANGES);
+
+        assert_eq!(parse_changes(&content).unwrap().changes.len(), 1);
+    }
+
+    #[test]
+    fn parses_the_first_object_in_prose() {
+        let content = format!("The fix is {} and should {{hopefully}} work.", CHANGES);
+
+        assert_eq!(parse_changes(&content).unwrap().changes.len(), 1);
+    }
+
+    #[test]
+    fn skips_braces_inside_strings() {
+        let content =
+            r#"Sure: {"changes": [{"file": "src/main.rs", "diff": "@@ @@\n-}\n+{ x }\n"}]} done"#;
+
+        let changes = parse_changes(content).unwrap();
+
+        assert_eq!(changes.changes[0].diff, "@@ @@\n-}\n+{ x }\n");
+    }
+
+    #[test]
+    fn accepts_a_bare_list_of_changes() {
+        let content = r#"[{"file": "src/main.rs", "diff": "@@ @@\n-a\n+b\n"}]"#;
+
+        let changes = parse_changes(content).unwrap();
+
+        assert_eq!(changes.changes[0].time_estimate_seconds, 0);
+    }
+
+    #[test]
+    fn reports_a_response_without_json() {
+        assert_eq!(error("I can't fix this, sorry."), ResponseError::NoJson);
+    }
+
+    #[test]
+    fn reports_where_the_json_is_malformed() {
+        let ResponseError::Syntax { line, reason, .. } =
+            error("{\n  \"changes\": [\n    oops\n  ]\n}")
+        else {
+            panic!("expected a syntax error");
+        };
+
+        assert_eq!(line, 3);
+        assert!(!reason.contains(" at line "));
+    }
+
+    #[test]
+    fn points_at_the_value_with_the_wrong_shape() {
+        let cases = [
+            (r#"{"diff": "x"}"#, "changes", "is missing"),
+            (
+                r#"{"changes": {}}"#,
+                "changes",
+                "should be an array, found an object",
+            ),
+            (
+                r#"{"changes": [1]}"#,
+                "changes[0]",
+                "should be an object, found a number",
+            ),
+            (
+                r#"{"changes": [{"diff": "x"}]}"#,
+                "changes[0].file",
+                "is missing",
+            ),
+            (
+                r#"{"changes": [{"file": "a.rs", "diff": " "}]}"#,
+                "changes[0].diff",
+                "is empty",
+            ),
+            (
+                r#"{"changes": [{"file": "a.rs", "diff": ["x"]}]}"#,
+                "changes[0].diff",
+                "should be a string, found an array",
+            ),
+            (
+                r#"{"changes": [{"file": "a.rs", "diff": "x"}, {"file": "b.rs", "diff": "x", "time_estimate_seconds": -1}]}"#,
+                "changes[1].time_estimate_seconds",
+                "should be a whole number of seconds, found a number",
+            ),
+            (
+                "```\n\"changes\"\n```",
+                "$",
+                "should be an object, found a string",
+            ),
+        ];
+
+        for (content, path, reason) in cases {
+            assert_eq!(
+                error(content),
+                ResponseError::Schema {
+                    path: path.to_string(),
+                    reason: reason.to_string()
+                },
+                "{}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn parses_an_explanation() {
+        let content = r#"```json
+{"explanation": "a", "root_cause": "b", "suggested_fix": "c"}
+```"#;
+
+        assert_eq!(
+            parse_explanation(content).unwrap(),
+            Explanation {
+                explanation: String::from("a"),
+                root_cause: String::from("b"),
+                suggested_fix: String::from("c"),
+            }
+        );
+    }
+
+    #[test]
+    fn points_at_the_explanation_field_with_the_wrong_shape() {
+        let content = r#"{"explanation": "a", "root_cause": 1, "suggested_fix": "c"}"#;
+
+        assert_eq!(
+            parse_explanation(content).unwrap_err(),
+            ResponseError::Schema {
+                path: String::from("root_cause"),
+                reason: String::from("should be a string, found a number"),
+            }
+        );
+    }
+}
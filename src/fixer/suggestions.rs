@@ -0,0 +1,161 @@
+use colored::Colorize;
+use linked_hash_map::LinkedHashMap;
+
+use crate::{
+    config::Config,
+    diagnostics::diagnostic::{Applicability, Diagnostic},
+    patch::{hunk::FilePatch, review::Review, rollback::Snapshot},
+    workspace::Workspace,
+};
+
+/// A replacement rustc is certain about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    fn overlaps(&self, other: &Suggestion) -> bool {
+        self.file == other.file
+            && self.byte_start < other.byte_end.max(other.byte_start + 1)
+            && other.byte_start < self.byte_end.max(self.byte_start + 1)
+    }
+}
+
+/// Every machine-applicable suggestion attached to a diagnostic or its children.
+///
+/// They belong together, e.g. adding an import and using it, so they should be applied all or nothing.
+pub fn machine_applicable(diagnostic: &Diagnostic) -> Vec<Suggestion> {
+    diagnostic
+        .all_spans()
+        .into_iter()
+        .filter(|span| span.suggestion_applicability == Some(Applicability::MachineApplicable))
+        .filter_map(|span| {
+            Some(Suggestion {
+                file: span.file_name.clone(),
+                byte_start: span.byte_start,
+                byte_end: span.byte_end,
+                replacement: span.suggested_replacement.clone()?,
+            })
+        })
+        .collect()
+}
+
+/// Applies the machine-applicable suggestions of `diagnostics`, the same way `cargo fix` does.
+///
+/// Returns a snapshot of the touched files and the diagnostics whose suggestions were written.
+pub fn apply_suggestions(
+    config: &Config,
+    diagnostics: &[Diagnostic],
+) -> (Snapshot, Vec<Diagnostic>) {
+    let mut snapshot = Snapshot::new();
+
+    let mut accepted: Vec<Suggestion> = Vec::new();
+    let mut applied: Vec<Diagnostic> = Vec::new();
+
+    for diagnostic in diagnostics {
+        let suggestions = machine_applicable(diagnostic);
+
+        // Overlapping suggestions come from separate diagnostics about the same code, the first one wins
+        if suggestions.is_empty()
+            || suggestions
+                .iter()
+                .any(|suggestion| accepted.iter().any(|other| other.overlaps(suggestion)))
+        {
+            continue;
+        }
+
+        // Suggestions for dependencies, e.g. a `pub` on a type in a registry crate, can't be taken
+        if suggestions
+            .iter()
+            .any(|suggestion| !Workspace::current().is_editable(&suggestion.file))
+        {
+            continue;
+        }
+
+        accepted.extend(suggestions);
+        applied.push(diagnostic.clone());
+    }
+
+    let mut by_file: LinkedHashMap<String, Vec<Suggestion>> = LinkedHashMap::new();
+
+    for suggestion in accepted {
+        by_file
+            .entry(suggestion.file.clone())
+            .or_default()
+            .push(suggestion);
+    }
+
+    let mut review = Review::new(config.auto_apply);
+
+    for (file, mut suggestions) in by_file {
+        let Ok(contents) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+
+        // Replacing from the end keeps the byte offsets of earlier suggestions valid
+        suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.byte_start));
+
+        let mut patched = contents.clone();
+
+        for suggestion in &suggestions {
+            if suggestion.byte_end > patched.len()
+                || !patched.is_char_boundary(suggestion.byte_start)
+                || !patched.is_char_boundary(suggestion.byte_end)
+            {
+                continue;
+            }
+
+            patched.replace_range(
+                suggestion.byte_start..suggestion.byte_end,
+                &suggestion.replacement,
+            );
+        }
+
+        // Suggestions are shown for review like any other change
+        let Some(patch) = FilePatch::from_contents(&file, &contents, &patched) else {
+            continue;
+        };
+
+        let Some(patch) = review.review(&patch) else {
+            continue;
+        };
+
+        if let Ok(patched) = patch.apply(&contents) {
+            // The file is left out of the snapshot, so its diagnostics don't count as applied
+            if let Err(err) = std::fs::write(&file, patched) {
+                println!("{} Unable to write {}: {}", ">".bright_black(), file, err);
+
+                // A write that failed halfway may have left the file truncated
+                let _ = std::fs::write(&file, &contents);
+                continue;
+            }
+
+            println!(
+                "{} Applying {} compiler {} to {}",
+                ">".bright_black(),
+                suggestions.len(),
+                if suggestions.len() == 1 {
+                    "suggestion"
+                } else {
+                    "suggestions"
+                },
+                file.bright_yellow()
+            );
+
+            snapshot.record_contents(&file, Some(contents));
+        }
+    }
+
+    // Diagnostics whose files were left alone weren't applied after all
+    applied.retain(|diagnostic| {
+        machine_applicable(diagnostic)
+            .iter()
+            .all(|suggestion| snapshot.files().contains(&&suggestion.file))
+    });
+
+    (snapshot, applied)
+}
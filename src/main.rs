@@ -2,7 +2,12 @@ pub mod cli;
 pub mod commands;
 pub mod config;
 pub mod constants;
+pub mod diagnostics;
+pub mod fixer;
 pub mod models;
+pub mod patch;
+pub mod providers;
+pub mod workspace;
 
 use cli::parser;
 use dotenv::dotenv;
@@ -1,10 +1,14 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
+use crate::providers::provider::ProviderKind;
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Model {
     GPT4,
     GPT3Turbo,
-    ClaudeV1,
+    Claude35Sonnet,
 }
 
 impl Model {
@@ -12,24 +16,49 @@ impl Model {
         match input {
             "🤖 GPT 4.0" => Self::GPT4,
             "🐇 GPT 3.5 Turbo" => Self::GPT3Turbo,
-            "💫 Claude v1" => Self::ClaudeV1,
+            "💫 Claude 3.5 Sonnet" => Self::Claude35Sonnet,
             _ => panic!("Invalid model"),
         }
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn code(&self) -> String {
         match self {
-            Self::GPT4 => "GPT 4.0".to_string(),
-            Self::GPT3Turbo => "GPT 3.5 Turbo".to_string(),
-            Self::ClaudeV1 => "Claude v1".to_string(),
+            Self::GPT4 => "gpt-4".to_string(),
+            Self::GPT3Turbo => "gpt-3.5-turbo".to_string(),
+            Self::Claude35Sonnet => "claude-3-5-sonnet-20241022".to_string(),
         }
     }
 
-    pub fn code(&self) -> String {
+    /// The provider that serves this model unless `neura.toml` says otherwise
+    pub fn provider(&self) -> ProviderKind {
         match self {
-            Self::GPT4 => "gpt-4".to_string(),
-            Self::GPT3Turbo => "gpt-3.5-turbo".to_string(),
-            Self::ClaudeV1 => "claude-v1".to_string(),
+            Self::GPT4 | Self::GPT3Turbo => ProviderKind::OpenAi,
+            Self::Claude35Sonnet => ProviderKind::Anthropic,
+        }
+    }
+
+    /// How many tokens the model can take in, prompt and response together
+    pub fn context_window(&self) -> usize {
+        match self {
+            Self::GPT4 => 8192,
+            Self::GPT3Turbo => 4096,
+            Self::Claude35Sonnet => 200000,
+        }
+    }
+
+    /// How many tokens the model is allowed to respond with
+    pub fn max_output_tokens(&self) -> usize {
+        match self {
+            Self::GPT4 => 2048,
+            Self::GPT3Turbo => 1024,
+            Self::Claude35Sonnet => 8192,
+        }
+    }
+
+    /// Whether the model can answer through function calling / tool use
+    pub fn supports_tools(&self) -> bool {
+        match self {
+            Self::GPT4 | Self::GPT3Turbo | Self::Claude35Sonnet => true,
         }
     }
 
@@ -37,8 +66,34 @@ impl Model {
         match code {
             "gpt-4" => Self::GPT4,
             "gpt-3.5-turbo" => Self::GPT3Turbo,
-            "claude-v1" => Self::ClaudeV1,
+            // Claude v1 isn't served by the Messages API, configs that still ask for it get its successor
+            "claude-3-5-sonnet-20241022" | "claude-v1" => Self::Claude35Sonnet,
             _ => panic!("Invalid model"),
         }
     }
 }
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GPT4 => write!(f, "GPT 4.0"),
+            Self::GPT3Turbo => write!(f, "GPT 3.5 Turbo"),
+            Self::Claude35Sonnet => write!(f, "Claude 3.5 Sonnet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_asking_for_claude_v1_get_a_model_the_messages_api_serves() {
+        let model = Model::from_code("claude-v1");
+
+        assert_eq!(model, Model::Claude35Sonnet);
+        assert_eq!(model.code(), "claude-3-5-sonnet-20241022");
+        assert_eq!(model.provider(), ProviderKind::Anthropic);
+        assert_eq!(Model::from_code(&model.code()), model);
+    }
+}
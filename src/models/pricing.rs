@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// What a model charges, in dollars per 1K tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl Price {
+    /// What a request costs in dollars, given its token counts
+    pub fn cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        (prompt_tokens as f64 / 1000.0) * self.prompt
+            + (completion_tokens as f64 / 1000.0) * self.completion
+    }
+}
+
+// List prices of hosted models, keyed on the id the provider knows them by.
+// Dated snapshots like `gpt-4-0613` use the price of the longest id they start with.
+const PRICES: [(&str, Price); 12] = [
+    ("gpt-4", price(0.03, 0.06)),
+    ("gpt-4-32k", price(0.06, 0.12)),
+    ("gpt-4-turbo", price(0.01, 0.03)),
+    ("gpt-4o", price(0.0025, 0.01)),
+    ("gpt-4o-mini", price(0.00015, 0.0006)),
+    ("gpt-3.5-turbo", price(0.0005, 0.0015)),
+    ("gpt-3.5-turbo-16k", price(0.003, 0.004)),
+    ("claude-2", price(0.008, 0.024)),
+    ("claude-3-opus", price(0.015, 0.075)),
+    ("claude-3-sonnet", price(0.003, 0.015)),
+    ("claude-3-5-sonnet", price(0.003, 0.015)),
+    ("claude-3-haiku", price(0.00025, 0.00125)),
+];
+
+const fn price(prompt: f64, completion: f64) -> Price {
+    Price { prompt, completion }
+}
+
+/// The list price of the model with the given id, `None` for models neura doesn't know the price of
+pub fn list_price(model: &str) -> Option<Price> {
+    PRICES
+        .iter()
+        .filter(|(id, _)| model.starts_with(id))
+        .max_by_key(|(id, _)| id.len())
+        .map(|(_, price)| *price)
+}
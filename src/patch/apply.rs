@@ -0,0 +1,342 @@
+use super::{
+    error::PatchError,
+    hunk::{FilePatch, Hunk, Line},
+};
+
+// How many context lines may be dropped from each end of a hunk that doesn't match as-is
+const MAX_FUZZ: usize = 2;
+
+// Ways of comparing a line from the hunk with a line from the file, from strictest to loosest
+const COMPARISONS: [fn(&str, &str) -> bool; 3] = [
+    |a, b| a == b,
+    |a, b| a.trim_end() == b.trim_end(),
+    |a, b| a.trim() == b.trim(),
+];
+
+impl FilePatch {
+    /// Applies every hunk to `contents`, returning the patched contents.
+    ///
+    /// Hunks are applied in order and all of them have to apply, otherwise nothing is changed.
+    pub fn apply(&self, contents: &str) -> Result<String, PatchError> {
+        let trailing_newline = contents.ends_with('\n') || contents.is_empty();
+
+        // `lines()` drops the `\r` of CRLF endings, so they're put back when joining
+        let line_ending = if contents.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+
+        let mut lines = contents.lines().map(String::from).collect::<Vec<String>>();
+
+        // Hunks may not touch anything before the end of the previously applied hunk
+        let mut floor = 0;
+
+        // How far earlier hunks have shifted the line numbers
+        let mut offset: isize = 0;
+
+        for (index, hunk) in self.hunks.iter().enumerate() {
+            let (hunk, position) = self.locate(index, hunk, &lines, floor, offset)?;
+
+            let old_len = hunk.old_lines().len();
+
+            // Context lines keep the file's own version, which may differ in whitespace from the hunk
+            let mut cursor = position;
+            let mut replacement = Vec::new();
+
+            for line in &hunk.lines {
+                match line {
+                    Line::Context(_) => {
+                        replacement.push(lines[cursor].clone());
+                        cursor += 1;
+                    }
+                    Line::Remove(_) => cursor += 1,
+                    Line::Add(text) => replacement.push(text.clone()),
+                }
+            }
+
+            let new_len = replacement.len();
+
+            lines.splice(position..position + old_len, replacement);
+
+            floor = position + new_len;
+            offset += new_len as isize - old_len as isize;
+        }
+
+        let mut patched = lines.join(line_ending);
+
+        if trailing_newline {
+            patched.push_str(line_ending);
+        }
+
+        Ok(patched)
+    }
+
+    // Finds where a hunk applies, loosening the match step by step
+    fn locate(
+        &self,
+        index: usize,
+        hunk: &Hunk,
+        lines: &[String],
+        floor: usize,
+        offset: isize,
+    ) -> Result<(Hunk, usize), PatchError> {
+        for fuzz in 0..=MAX_FUZZ {
+            let Some(hunk) = hunk.without_context(fuzz) else {
+                break;
+            };
+
+            let hint = hunk
+                .old_start
+                .map(|start| (start as isize - 1 + offset).max(0) as usize);
+
+            let old = hunk.old_lines();
+
+            // Pure insertions have nothing to match against, so they go where the header says
+            if old.is_empty() {
+                let position = hint.unwrap_or(lines.len()).clamp(floor, lines.len());
+
+                return Ok((hunk, position));
+            }
+
+            if let Some(position) = find(lines, &old, hint, floor) {
+                return Ok((hunk, position));
+            }
+
+            // The hunk exists, but only in a part of the file an earlier hunk already changed
+            if find(lines, &old, hint, 0).is_some() {
+                return Err(PatchError::Overlap {
+                    file: self.file.clone(),
+                    hunk: index,
+                });
+            }
+        }
+
+        Err(PatchError::NotFound {
+            file: self.file.clone(),
+            hunk: index,
+        })
+    }
+}
+
+// Finds the position of `needle` at or after `floor`, preferring the match closest to `hint`
+fn find(lines: &[String], needle: &[&str], hint: Option<usize>, floor: usize) -> Option<usize> {
+    if needle.len() > lines.len() {
+        return None;
+    }
+
+    for comparison in COMPARISONS {
+        let candidates = (floor..=lines.len() - needle.len())
+            .filter(|start| {
+                needle
+                    .iter()
+                    .enumerate()
+                    .all(|(i, line)| comparison(&lines[start + i], line))
+            })
+            .collect::<Vec<usize>>();
+
+        let best = match hint {
+            Some(hint) => candidates
+                .into_iter()
+                .min_by_key(|start| start.abs_diff(hint)),
+            None => candidates.into_iter().next(),
+        };
+
+        if best.is_some() {
+            return best;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: &str = "fn main() {\n    let x = 1;\n    println!(x);\n}\n";
+
+    fn apply(diff: &str, contents: &str) -> Result<String, PatchError> {
+        FilePatch::parse("src/main.rs", diff)?.apply(contents)
+    }
+
+    #[test]
+    fn applies_an_exact_hunk() {
+        let diff =
+            "@@ -2,2 +2,2 @@\n     let x = 1;\n-    println!(x);\n+    println!(\"{}\", x);\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn applies_a_hunk_without_line_numbers() {
+        let diff = "@@ @@\n-    println!(x);\n+    println!(\"{}\", x);\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn ignores_wrong_line_numbers() {
+        let diff =
+            "@@ -40,2 +40,2 @@\n     let x = 1;\n-    println!(x);\n+    println!(\"{}\", x);\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn prefers_the_match_closest_to_the_line_number() {
+        let contents = "a\nx\nb\na\nx\nb\n";
+        let diff = "@@ -5,1 +5,1 @@\n-x\n+y\n";
+
+        assert_eq!(apply(diff, contents).unwrap(), "a\nx\nb\na\ny\nb\n");
+    }
+
+    #[test]
+    fn drops_context_that_does_not_match_up_to_max_fuzz() {
+        let diff = "@@ -1,6 +1,6 @@\n\
+                    \x20// wrong\n\
+                    \x20fn main() { // wrong\n\
+                    \x20    let x = 1;\n\
+                    -    println!(x);\n\
+                    +    println!(\"{}\", x);\n\
+                    \x20}\n\
+                    \x20// wrong\n\
+                    \x20// wrong\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn gives_up_on_context_beyond_max_fuzz() {
+        let diff = "@@ -1,7 +1,7 @@\n\
+                    \x20// wrong\n\
+                    \x20// wrong\n\
+                    \x20// wrong\n\
+                    -    println!(x);\n\
+                    +    println!(\"{}\", x);\n\
+                    \x20// wrong\n\
+                    \x20// wrong\n\
+                    \x20// wrong\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap_err(),
+            PatchError::NotFound {
+                file: String::from("src/main.rs"),
+                hunk: 0
+            }
+        );
+    }
+
+    #[test]
+    fn matches_lines_with_different_trailing_whitespace() {
+        let contents = "fn main() {  \n    let x = 1;\t\n}\n";
+        let diff = "@@ -1,3 +1,3 @@\n fn main() {\n-    let x = 1;\n+    let x = 2;\n }\n";
+
+        // The context line keeps the file's own whitespace
+        assert_eq!(
+            apply(diff, contents).unwrap(),
+            "fn main() {  \n    let x = 2;\n}\n"
+        );
+    }
+
+    #[test]
+    fn matches_lines_with_different_indentation() {
+        let diff = "@@ -2,2 +2,2 @@\n let x = 1;\n-println!(x);\n+    println!(\"{}\", x);\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn rejects_hunks_that_overlap() {
+        let contents = "a\nb\nc\n";
+        let diff = "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -1,1 +1,1 @@\n-a\n+A\n";
+
+        assert_eq!(
+            apply(diff, contents).unwrap_err(),
+            PatchError::Overlap {
+                file: String::from("src/main.rs"),
+                hunk: 1
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_hunks_whose_context_is_missing() {
+        let diff =
+            "@@ -2,2 +2,2 @@\n     let y = 2;\n-    println!(y);\n+    println!(\"{}\", y);\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap_err(),
+            PatchError::NotFound {
+                file: String::from("src/main.rs"),
+                hunk: 0
+            }
+        );
+    }
+
+    #[test]
+    fn applies_every_hunk_in_order() {
+        let contents = "a\nb\nc\nd\ne\nf\ng\n";
+        let diff = "@@ -1,2 +1,2 @@\n a\n-b\n+B\n@@ -6,2 +6,3 @@\n f\n+F\n g\n";
+
+        assert_eq!(apply(diff, contents).unwrap(), "a\nB\nc\nd\ne\nf\nF\ng\n");
+    }
+
+    #[test]
+    fn keeps_a_missing_trailing_newline_missing() {
+        let contents = "fn main() {\n    let x = 1;\n}";
+        let diff =
+            "@@ -2,2 +2,2 @@\n-    let x = 1;\n+    let x = 2;\n }\n\\ No newline at end of file\n";
+
+        assert_eq!(
+            apply(diff, contents).unwrap(),
+            "fn main() {\n    let x = 2;\n}"
+        );
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let contents = "fn main() {\r\n    let x = 1;\r\n}\r\n";
+        let diff = "@@ -2,1 +2,2 @@\n-    let x = 1;\n+    let x = 2;\n+    let y = 3;\n";
+
+        assert_eq!(
+            apply(diff, contents).unwrap(),
+            "fn main() {\r\n    let x = 2;\r\n    let y = 3;\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn inserts_pure_additions_after_the_line_in_the_header() {
+        let diff = "@@ -1,0 +2,1 @@\n+    // added\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "fn main() {\n    // added\n    let x = 1;\n    println!(x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn inserts_pure_additions_at_the_top_of_the_file() {
+        let diff = "@@ -0,0 +1,1 @@\n+use std::fmt;\n";
+
+        assert_eq!(
+            apply(diff, MAIN).unwrap(),
+            "use std::fmt;\nfn main() {\n    let x = 1;\n    println!(x);\n}\n"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The diff text could not be parsed
+    Malformed { file: String, reason: String },
+
+    /// A hunk's context could not be found in the file
+    NotFound { file: String, hunk: usize },
+
+    /// A hunk would overlap a hunk that has already been applied
+    Overlap { file: String, hunk: usize },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed { file, reason } => {
+                write!(f, "malformed diff for {}: {}", file, reason)
+            }
+            Self::NotFound { file, hunk } => write!(
+                f,
+                "hunk #{} does not match the contents of {}",
+                hunk + 1,
+                file
+            ),
+            Self::Overlap { file, hunk } => {
+                write!(f, "hunk #{} overlaps an earlier hunk in {}", hunk + 1, file)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
@@ -0,0 +1,314 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::{config::GitConfig, diagnostics::diagnostic::Diagnostic};
+
+use super::rollback::Snapshot;
+
+// Fix branches are called `neura/fix-<sha>`, after the commit they start from
+const BRANCH_PREFIX: &str = "neura/fix-";
+
+/// What to do when tracked files have uncommitted changes before neura edits anything
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum DirtyPolicy {
+    /// Stop and ask for the changes to be committed first
+    #[default]
+    #[serde(rename = "refuse")]
+    Refuse,
+
+    /// Save a copy of the changes in the stash, leaving them in place
+    #[serde(rename = "stash")]
+    Stash,
+
+    /// Edit files with uncommitted changes anyway
+    #[serde(rename = "allow")]
+    Allow,
+}
+
+/// Gets the repository ready before any file is edited: handles uncommitted changes as `config.dirty`
+/// says, then switches to the fix branch when `config.branch` is set. Does nothing unless git is enabled.
+pub fn prepare(config: &GitConfig) -> miette::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    if git(&["rev-parse", "--is-inside-work-tree"]).is_none() {
+        return Err(miette::miette!(
+            "git integration is turned on, but the current directory isn't in a git repository"
+        ));
+    }
+
+    let Some(head) = git(&["rev-parse", "--short", "HEAD"]) else {
+        return Err(miette::miette!(
+            "git integration needs a commit to start from, the repository has none yet"
+        ));
+    };
+
+    // Untracked files, like `.env`, are never touched by a fix
+    let changed: Vec<String> = git(&["status", "--porcelain", "--untracked-files=no"])
+        .unwrap_or_default()
+        .lines()
+        // The output is trimmed, so the first line may have lost the space its status starts with
+        .filter_map(|line| line.get(2..))
+        .map(|path| path.trim_start().to_string())
+        .collect();
+
+    if !changed.is_empty() {
+        match config.dirty {
+            DirtyPolicy::Refuse => {
+                return Err(miette::miette!(
+                    "there are uncommitted changes in {}, commit them first or set `dirty = \"stash\"` under `[git]` in neura.toml",
+                    changed.join(", ")
+                ));
+            }
+            DirtyPolicy::Stash => {
+                // `stash create` makes the stash commit without resetting the working tree
+                let stashed = git(&["stash", "create"])
+                    .filter(|commit| !commit.is_empty())
+                    .filter(|commit| {
+                        git(&[
+                            "stash",
+                            "store",
+                            "-m",
+                            "neura: uncommitted changes before fixing",
+                            commit,
+                        ])
+                        .is_some()
+                    });
+
+                if stashed.is_none() {
+                    return Err(miette::miette!(
+                        "couldn't save your uncommitted changes to the stash"
+                    ));
+                }
+
+                println!(
+                    "📦 Saved a copy of your uncommitted changes in {}, they are still in place.",
+                    "stash@{0}".bright_cyan()
+                );
+            }
+            DirtyPolicy::Allow => {}
+        }
+    }
+
+    if !config.branch {
+        return Ok(());
+    }
+
+    let current = git(&["branch", "--show-current"]).unwrap_or_default();
+
+    // A session that starts on a fix branch carries on with it
+    if current.starts_with(BRANCH_PREFIX) {
+        return Ok(());
+    }
+
+    let branch = format!("{}{}", BRANCH_PREFIX, head);
+
+    let switched = if git(&["rev-parse", "--verify", "--quiet", &branch]).is_some() {
+        git(&["switch", &branch])
+    } else {
+        git(&["switch", "-c", &branch])
+    };
+
+    if switched.is_none() {
+        return Err(miette::miette!(
+            "couldn't switch to the `{}` branch",
+            branch
+        ));
+    }
+
+    println!("🌿 Fixes go on the {} branch.", branch.bright_cyan());
+
+    Ok(())
+}
+
+/// Commits what a fix changed in the files of `snapshot`, with `subject` and the `diagnostics` it was for in the message.
+///
+/// Only the fix is committed, not other changes to the same files or whatever is staged already,
+/// so every fix can be reverted on its own. Does nothing unless git is enabled with `commit` set.
+pub fn commit_fix(
+    config: &GitConfig,
+    snapshot: &Snapshot,
+    subject: &str,
+    diagnostics: &[&Diagnostic],
+) {
+    if !config.enabled || !config.commit {
+        return;
+    }
+
+    let message = format!(
+        "{}\n\n{}\n",
+        subject,
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.rendered().trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    );
+
+    match commit(snapshot, &message) {
+        Some(commit) => println!("📝 Committed {} {}", commit.bright_cyan(), subject),
+        None => println!(
+            "⚠️  Couldn't commit the fix for {} on its own, it's left uncommitted.",
+            diagnostics
+                .first()
+                .map(|diagnostic| diagnostic.headline())
+                .unwrap_or_default()
+                .bright_red()
+        ),
+    }
+}
+
+// Builds the commit in a separate index, starting from `HEAD`, and moves the branch to it
+fn commit(snapshot: &Snapshot, message: &str) -> Option<String> {
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?);
+    let root = std::fs::canonicalize(&root).unwrap_or(root);
+
+    let patch = patch(snapshot, &root)?;
+
+    if patch.is_empty() {
+        return None;
+    }
+
+    let head = git(&["rev-parse", "HEAD"])?;
+    let index = PathBuf::from(git(&["rev-parse", "--absolute-git-dir"])?).join("neura-index");
+
+    let in_index = |args: &[&str], input: Option<&str>| {
+        let mut command = Command::new("git");
+        command
+            .args(args)
+            .current_dir(&root)
+            .env("GIT_INDEX_FILE", &index);
+
+        run(command, input)
+    };
+
+    let tree = in_index(&["read-tree", "HEAD"], None)
+        .and_then(|_| in_index(&["apply", "--cached", "-"], Some(&patch)))
+        .and_then(|_| in_index(&["write-tree"], None));
+
+    let _ = std::fs::remove_file(&index);
+
+    let commit = git_in(
+        &root,
+        &["commit-tree", &tree?, "-p", &head, "-F", "-"],
+        Some(message),
+    )?;
+
+    // Fails if `HEAD` moved in the meantime, instead of dropping whatever was committed
+    git_in(
+        &root,
+        &["update-ref", "-m", "neura: fix", "HEAD", &commit, &head],
+        None,
+    )?;
+
+    // The fix is in `HEAD` now, so it's staged too, or it would show up as reverted in `git status`
+    let _ = git_in(&root, &["apply", "--cached", "-"], Some(&patch));
+
+    git(&["rev-parse", "--short", &commit])
+}
+
+// A patch from the recorded contents of every file in `snapshot` to what's on disk now, with paths relative to `root`
+fn patch(snapshot: &Snapshot, root: &Path) -> Option<String> {
+    let mut patch = String::new();
+
+    for (file, before) in snapshot.contents() {
+        let after = std::fs::read_to_string(file).ok();
+
+        if *before == after {
+            continue;
+        }
+
+        // The file may only be gone when it has been deleted, then its directory is what exists
+        let path = Path::new(file);
+        let absolute = match std::fs::canonicalize(path) {
+            Ok(absolute) => absolute,
+            Err(_) => std::fs::canonicalize(path.parent()?)
+                .ok()?
+                .join(path.file_name()?),
+        };
+
+        let relative = absolute
+            .strip_prefix(root)
+            .ok()?
+            .to_string_lossy()
+            .to_string();
+
+        let old = before.as_deref().unwrap_or_default();
+        let new = after.as_deref().unwrap_or_default();
+
+        let old_header = match before {
+            Some(_) => format!("a/{}", relative),
+            None => String::from("/dev/null"),
+        };
+        let new_header = match after {
+            Some(_) => format!("b/{}", relative),
+            None => String::from("/dev/null"),
+        };
+
+        patch.push_str(&format!("diff --git a/{} b/{}\n", relative, relative));
+
+        if before.is_none() {
+            patch.push_str("new file mode 100644\n");
+        } else if after.is_none() {
+            patch.push_str("deleted file mode 100644\n");
+        }
+
+        patch.push_str(
+            &TextDiff::from_lines(old, new)
+                .unified_diff()
+                .header(&old_header, &new_header)
+                .to_string(),
+        );
+    }
+
+    Some(patch)
+}
+
+// Runs git in the current directory, returning its trimmed output or `None` when it fails
+fn git(args: &[&str]) -> Option<String> {
+    let mut command = Command::new("git");
+    command.args(args);
+
+    run(command, None)
+}
+
+fn git_in(directory: &Path, args: &[&str], input: Option<&str>) -> Option<String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(directory);
+
+    run(command, input)
+}
+
+fn run(mut command: Command, input: Option<&str>) -> Option<String> {
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    if let Some(input) = input {
+        // The pipe is closed when `stdin` is dropped, so git knows the input is over
+        let mut stdin = child.stdin.take()?;
+        stdin.write_all(input.as_bytes()).ok()?;
+    }
+
+    let output = child.wait_with_output().ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
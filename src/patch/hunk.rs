@@ -0,0 +1,427 @@
+use std::fmt;
+
+use super::error::PatchError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// 1-based line the hunk claims to start at, only used as a hint when locating it.
+    ///
+    /// For a pure insertion this is the line the new lines go before.
+    pub old_start: Option<usize>,
+    pub lines: Vec<Line>,
+}
+
+impl Hunk {
+    /// Lines the hunk expects to find in the file
+    pub fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Remove(text) => Some(text.as_str()),
+                Line::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Lines the hunk leaves behind once applied
+    pub fn new_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Add(text) => Some(text.as_str()),
+                Line::Remove(_) => None,
+            })
+            .collect()
+    }
+
+    /// Whether the hunk changes anything at all
+    pub fn is_noop(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, Line::Context(_)))
+    }
+
+    /// Returns the hunk without `fuzz` context lines on either end.
+    ///
+    /// Returns `None` when there aren't enough context lines to drop.
+    pub fn without_context(&self, fuzz: usize) -> Option<Hunk> {
+        if fuzz == 0 {
+            return Some(self.clone());
+        }
+
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+
+        if leading < fuzz || trailing < fuzz || self.is_noop() {
+            return None;
+        }
+
+        let hunk = Hunk {
+            old_start: self.old_start.map(|start| start + fuzz),
+            lines: self.lines[fuzz..self.lines.len() - fuzz].to_vec(),
+        };
+
+        Some(hunk)
+    }
+}
+
+impl fmt::Display for Hunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old_len = self.old_lines().len();
+
+        match self.old_start {
+            // A pure insertion's header names the line it goes after
+            Some(start) if old_len == 0 => writeln!(
+                f,
+                "@@ -{},0 +{},{} @@",
+                start - 1,
+                start,
+                self.new_lines().len()
+            )?,
+            Some(start) => writeln!(
+                f,
+                "@@ -{},{} +{},{} @@",
+                start,
+                old_len,
+                start,
+                self.new_lines().len()
+            )?,
+            None => writeln!(f, "@@ @@")?,
+        }
+
+        for line in &self.lines {
+            match line {
+                Line::Context(text) => writeln!(f, " {}", text)?,
+                Line::Remove(text) => writeln!(f, "-{}", text)?,
+                Line::Add(text) => writeln!(f, "+{}", text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// All hunks that apply to a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePatch {
+    pub file: String,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// Parses a unified diff for `file`.
+    ///
+    /// Models are sloppy with diffs, so file headers are optional, hunk headers don't need line numbers
+    /// (`@@ ... @@` is enough) and a diff without any hunk header is treated as a single hunk.
+    pub fn parse(file: &str, diff: &str) -> Result<Self, PatchError> {
+        let lines = diff.trim_end_matches('\n').lines().collect::<Vec<&str>>();
+
+        let mut hunks: Vec<Hunk> = Vec::new();
+        let mut current: Option<Hunk> = None;
+
+        for (index, line) in lines.iter().enumerate() {
+            // File headers, `--- a/file` is only a header when followed by `+++ b/file`
+            if line.starts_with("diff ") || line.starts_with("index ") {
+                continue;
+            }
+
+            if line.starts_with("--- ")
+                && lines
+                    .get(index + 1)
+                    .is_some_and(|next| next.starts_with("+++ "))
+            {
+                continue;
+            }
+
+            if line.starts_with("+++ ") && index > 0 && lines[index - 1].starts_with("--- ") {
+                continue;
+            }
+
+            if line.starts_with("@@") {
+                if let Some(hunk) = current.take() {
+                    hunks.push(hunk);
+                }
+
+                current = Some(Hunk {
+                    old_start: parse_old_start(line),
+                    lines: Vec::new(),
+                });
+
+                continue;
+            }
+
+            // Not a line we care about
+            if line.starts_with("\\ No newline") {
+                continue;
+            }
+
+            let hunk = current.get_or_insert_with(|| Hunk {
+                old_start: None,
+                lines: Vec::new(),
+            });
+
+            let parsed = if let Some(text) = line.strip_prefix('+') {
+                Line::Add(text.to_string())
+            } else if let Some(text) = line.strip_prefix('-') {
+                Line::Remove(text.to_string())
+            } else if let Some(text) = line.strip_prefix(' ') {
+                Line::Context(text.to_string())
+            } else {
+                // Models often drop the leading space on context lines, matching will reject it if it's wrong
+                Line::Context(line.to_string())
+            };
+
+            hunk.lines.push(parsed);
+        }
+
+        if let Some(hunk) = current.take() {
+            hunks.push(hunk);
+        }
+
+        hunks.retain(|hunk| !hunk.is_noop());
+
+        if hunks.is_empty() {
+            return Err(PatchError::Malformed {
+                file: file.to_string(),
+                reason: String::from("the diff contains no changes"),
+            });
+        }
+
+        Ok(Self {
+            file: file.to_string(),
+            hunks,
+        })
+    }
+
+    /// Builds the patch that turns `old` into `new`, or `None` if they're the same.
+    pub fn from_contents(file: &str, old: &str, new: &str) -> Option<Self> {
+        let diff = similar::TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .to_string();
+
+        Self::parse(file, &diff).ok()
+    }
+
+    /// The patch that undoes this one, turning the new contents back into the old ones
+    pub fn reversed(&self) -> Self {
+        let hunks = self
+            .hunks
+            .iter()
+            .map(|hunk| Hunk {
+                // The old start of the reversed hunk is the new start of this one, which isn't known
+                old_start: None,
+                lines: hunk
+                    .lines
+                    .iter()
+                    .map(|line| match line {
+                        Line::Context(text) => Line::Context(text.clone()),
+                        Line::Remove(text) => Line::Add(text.clone()),
+                        Line::Add(text) => Line::Remove(text.clone()),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            file: self.file.clone(),
+            hunks,
+        }
+    }
+}
+
+impl fmt::Display for FilePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- a/{}", self.file)?;
+        writeln!(f, "+++ b/{}", self.file)?;
+
+        for hunk in &self.hunks {
+            write!(f, "{}", hunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Reads the old start line out of a hunk header like `@@ -12,7 +12,8 @@`
+fn parse_old_start(header: &str) -> Option<usize> {
+    let range = header
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('-'))?;
+
+    let (start, count) = match range.split_once(',') {
+        Some((start, count)) => (start, count.parse::<usize>().ok()?),
+        None => (range, 1),
+    };
+    let start = start.parse::<usize>().ok()?;
+
+    // `-N,0` inserts after line N, so the new lines go before line N + 1 (`-0,0` is the top of the file)
+    match count {
+        0 => Some(start + 1),
+        _ => (start > 0).then_some(start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_full_unified_diff() {
+        let diff = "diff --git a/src/main.rs b/src/main.rs\n\
+                    index 83db48f..bf269f4 100644\n\
+                    --- a/src/main.rs\n\
+                    +++ b/src/main.rs\n\
+                    @@ -3,3 +3,3 @@ fn main() {\n\
+                    \x20    let x = 1;\n\
+                    -    println!(x);\n\
+                    +    println!(\"{}\", x);\n\
+                    \x20}\n";
+
+        let patch = FilePatch::parse("src/main.rs", diff).unwrap();
+
+        assert_eq!(patch.hunks.len(), 1);
+        assert_eq!(patch.hunks[0].old_start, Some(3));
+        assert_eq!(
+            patch.hunks[0].lines,
+            vec![
+                Line::Context(String::from("    let x = 1;")),
+                Line::Remove(String::from("    println!(x);")),
+                Line::Add(String::from("    println!(\"{}\", x);")),
+                Line::Context(String::from("}")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_hunks_without_line_numbers() {
+        let diff = "@@ ... @@\n a\n-b\n+c\n@@ @@\n d\n-e\n+f\n";
+
+        let patch = FilePatch::parse("lib.rs", diff).unwrap();
+
+        assert_eq!(patch.hunks.len(), 2);
+        assert!(patch.hunks.iter().all(|hunk| hunk.old_start.is_none()));
+        assert_eq!(patch.hunks[1].old_lines(), vec!["d", "e"]);
+        assert_eq!(patch.hunks[1].new_lines(), vec!["d", "f"]);
+    }
+
+    #[test]
+    fn treats_a_diff_without_hunk_headers_as_one_hunk() {
+        let patch = FilePatch::parse("lib.rs", " a\n-b\n+c\n").unwrap();
+
+        assert_eq!(patch.hunks.len(), 1);
+        assert_eq!(patch.hunks[0].old_start, None);
+        assert_eq!(patch.hunks[0].lines.len(), 3);
+    }
+
+    #[test]
+    fn keeps_context_lines_missing_their_leading_space() {
+        let patch = FilePatch::parse("lib.rs", "@@ -1,2 +1,2 @@\nfn a() {\n-b\n+c\n").unwrap();
+
+        assert_eq!(
+            patch.hunks[0].lines[0],
+            Line::Context(String::from("fn a() {"))
+        );
+    }
+
+    #[test]
+    fn a_removed_line_that_looks_like_a_file_header_is_kept() {
+        let patch = FilePatch::parse("lib.rs", "@@ -1,2 +1,1 @@\n--- a comment\n a\n").unwrap();
+
+        assert_eq!(
+            patch.hunks[0].lines[0],
+            Line::Remove(String::from("-- a comment"))
+        );
+    }
+
+    #[test]
+    fn skips_no_newline_markers() {
+        let diff =
+            "@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n";
+
+        let patch = FilePatch::parse("lib.rs", diff).unwrap();
+
+        assert_eq!(
+            patch.hunks[0].lines,
+            vec![
+                Line::Remove(String::from("a")),
+                Line::Add(String::from("b"))
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_diff_without_changes() {
+        let error = FilePatch::parse("lib.rs", "@@ -1,2 +1,2 @@\n a\n b\n").unwrap_err();
+
+        assert!(matches!(error, PatchError::Malformed { .. }));
+    }
+
+    #[test]
+    fn reads_the_old_start_line() {
+        assert_eq!(parse_old_start("@@ -12,7 +12,8 @@ fn main()"), Some(12));
+        assert_eq!(parse_old_start("@@ -5 +5 @@"), Some(5));
+        assert_eq!(parse_old_start("@@ ... @@"), None);
+    }
+
+    #[test]
+    fn pure_insertions_start_after_the_line_in_the_header() {
+        assert_eq!(parse_old_start("@@ -2,0 +3,1 @@"), Some(3));
+        assert_eq!(parse_old_start("@@ -0,0 +1,3 @@"), Some(1));
+
+        let patch = FilePatch::parse("lib.rs", "@@ -2,0 +3,1 @@\n+c\n").unwrap();
+
+        assert_eq!(
+            patch.to_string(),
+            "--- a/lib.rs\n+++ b/lib.rs\n@@ -2,0 +3,1 @@\n+c\n"
+        );
+    }
+
+    #[test]
+    fn drops_context_from_both_ends() {
+        let patch =
+            FilePatch::parse("lib.rs", "@@ -10,5 +10,5 @@\n a\n b\n-c\n+C\n d\n e\n").unwrap();
+        let hunk = &patch.hunks[0];
+
+        let trimmed = hunk.without_context(1).unwrap();
+
+        assert_eq!(trimmed.old_start, Some(11));
+        assert_eq!(trimmed.old_lines(), vec!["b", "c", "d"]);
+        assert!(hunk.without_context(3).is_none());
+    }
+
+    #[test]
+    fn reversing_swaps_additions_and_removals() {
+        let patch = FilePatch::parse("lib.rs", "@@ -1,2 +1,2 @@\n a\n-b\n+c\n").unwrap();
+
+        let reversed = patch.reversed();
+
+        assert_eq!(reversed.hunks[0].old_lines(), vec!["a", "c"]);
+        assert_eq!(reversed.hunks[0].new_lines(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let patch = FilePatch::from_contents("lib.rs", "a\nb\nc\n", "a\nB\nc\n").unwrap();
+
+        let reparsed = FilePatch::parse("lib.rs", &patch.to_string()).unwrap();
+
+        assert_eq!(reparsed, patch);
+    }
+}
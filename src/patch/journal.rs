@@ -0,0 +1,396 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::paths::DATA_DIRECTORY, diagnostics::diagnostic::Diagnostic};
+
+use super::{hunk::FilePatch, rollback::Snapshot};
+
+/// What a fix did to a single file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileFix {
+    pub file: String,
+
+    /// Hash of the contents before the fix, `None` if the fix created the file
+    pub original_hash: Option<String>,
+
+    /// Hash of the contents after the fix, `None` if the fix deleted the file
+    pub hash: Option<String>,
+
+    /// Unified diff from the original contents to the fixed ones
+    pub patch: String,
+}
+
+/// A fix that was kept, as recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+
+    /// The model that wrote the fix, or `rustc` for compiler suggestions
+    pub model: String,
+
+    /// The diagnostics the fix was for, as rustc renders them
+    pub diagnostics: Vec<String>,
+
+    pub files: Vec<FileFix>,
+
+    /// Whether the fix has been undone already
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// Why a fix can't be undone
+#[derive(Debug, Clone, PartialEq)]
+pub enum UndoError {
+    /// The file isn't what the fix left behind anymore
+    Changed { file: String },
+
+    /// Reverting the patch doesn't give back the original contents
+    Unrecoverable { file: String },
+
+    /// The file couldn't be written
+    Io { file: String, reason: String },
+}
+
+impl fmt::Display for UndoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Changed { file } => write!(f, "{} has changed since the fix", file),
+            Self::Unrecoverable { file } => {
+                write!(f, "the original contents of {} can't be recovered", file)
+            }
+            Self::Io { file, reason } => write!(f, "couldn't write {}: {}", file, reason),
+        }
+    }
+}
+
+impl std::error::Error for UndoError {}
+
+impl Entry {
+    /// The first line of the first diagnostic, e.g. `error[E0425]: cannot find value `x` in this scope`
+    pub fn headline(&self) -> &str {
+        self.diagnostics
+            .first()
+            .and_then(|diagnostic| diagnostic.lines().next())
+            .unwrap_or_default()
+    }
+
+    /// Puts every file back the way it was before the fix.
+    ///
+    /// Nothing is written unless every file is exactly as the fix left it and its original
+    /// contents can be recovered, so a fix is never half undone.
+    pub fn undo(&self) -> Result<(), UndoError> {
+        let mut originals: Vec<(&str, Option<String>)> = Vec::new();
+
+        for fix in &self.files {
+            let current = std::fs::read_to_string(&fix.file).ok();
+
+            if current.as_deref().map(sha256::digest) != fix.hash {
+                return Err(UndoError::Changed {
+                    file: fix.file.clone(),
+                });
+            }
+
+            let unrecoverable = || UndoError::Unrecoverable {
+                file: fix.file.clone(),
+            };
+
+            let original = match &fix.original_hash {
+                Some(original_hash) => {
+                    let original = FilePatch::parse(&fix.file, &fix.patch)
+                        .and_then(|patch| {
+                            patch
+                                .reversed()
+                                .apply(current.as_deref().unwrap_or_default())
+                        })
+                        .map_err(|_| unrecoverable())?;
+
+                    if sha256::digest(original.as_str()) != *original_hash {
+                        return Err(unrecoverable());
+                    }
+
+                    Some(original)
+                }
+                None => None,
+            };
+
+            originals.push((&fix.file, original));
+        }
+
+        for (file, original) in originals {
+            let written = match original {
+                Some(original) => std::fs::write(file, original),
+                None => std::fs::remove_file(file),
+            };
+
+            written.map_err(|error| UndoError::Io {
+                file: file.to_string(),
+                reason: error.to_string(),
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every fix neura kept, oldest first, stored in `.neura/journal.json` so it can be undone in a later session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    pub entries: Vec<Entry>,
+}
+
+impl Journal {
+    fn path() -> PathBuf {
+        Path::new(DATA_DIRECTORY).join("journal.json")
+    }
+
+    /// Reads the journal, which is empty when nothing has been recorded yet
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(DATA_DIRECTORY)?;
+
+        std::fs::write(Self::path(), serde_json::to_string_pretty(self)?)
+    }
+
+    /// The entry with the given id, or the only one whose id starts with it
+    pub fn find(&self, id: &str) -> Option<&Entry> {
+        let mut matching = self.entries.iter().filter(|entry| entry.id.starts_with(id));
+
+        match (matching.next(), matching.next()) {
+            (Some(entry), None) => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// The last `count` fixes that haven't been undone, newest first
+    pub fn last(&self, count: usize) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| !entry.undone)
+            .take(count)
+            .collect()
+    }
+
+    pub fn mark_undone(&mut self, id: &str) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.undone = true;
+        }
+    }
+
+    /// Records what a kept fix changed in the files of `snapshot`, returning its id.
+    ///
+    /// Returns `None` when the fix didn't change anything or the journal couldn't be written.
+    pub fn record(snapshot: &Snapshot, diagnostics: &[&Diagnostic], model: &str) -> Option<String> {
+        let entry = Self::entry(snapshot, diagnostics, model)?;
+        let id = entry.id.clone();
+
+        let mut journal = Self::load();
+
+        journal.entries.push(entry);
+        journal.save().ok()?;
+
+        Some(id)
+    }
+
+    // The entry for a kept fix, built from the files of `snapshot` as they are now
+    fn entry(snapshot: &Snapshot, diagnostics: &[&Diagnostic], model: &str) -> Option<Entry> {
+        let files: Vec<FileFix> = snapshot
+            .contents()
+            .filter_map(|(file, before)| {
+                let after = std::fs::read_to_string(file).ok();
+
+                let patch = FilePatch::from_contents(
+                    file,
+                    before.as_deref().unwrap_or_default(),
+                    after.as_deref().unwrap_or_default(),
+                )?;
+
+                Some(FileFix {
+                    file: file.clone(),
+                    original_hash: before.as_deref().map(sha256::digest),
+                    hash: after.as_deref().map(sha256::digest),
+                    patch: patch.to_string(),
+                })
+            })
+            .collect();
+
+        if files.is_empty() {
+            return None;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        let patches: Vec<&str> = files.iter().map(|fix| fix.patch.as_str()).collect();
+        let id = sha256::digest(format!("{}:{}", timestamp, patches.join("\n")))[..8].to_string();
+
+        Some(Entry {
+            id,
+            timestamp,
+            model: model.to_string(),
+            diagnostics: diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.rendered())
+                .collect(),
+            files,
+            undone: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for a single test, so tests running in parallel don't share files
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("neura-journal-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        directory
+    }
+
+    // Writes `after` over `before` in `file` and returns the entry recording the fix
+    fn fix(file: &Path, before: Option<&str>, after: &str) -> Entry {
+        let file = file.to_string_lossy();
+
+        if let Some(before) = before {
+            std::fs::write(file.as_ref(), before).unwrap();
+        }
+
+        let mut snapshot = Snapshot::new();
+        snapshot.record(&file);
+
+        std::fs::write(file.as_ref(), after).unwrap();
+
+        Journal::entry(&snapshot, &[], "gpt-4").unwrap()
+    }
+
+    #[test]
+    fn records_what_the_fix_changed() {
+        let directory = directory("record");
+        let file = directory.join("main.rs");
+
+        let entry = fix(&file, Some("let x = 1;\n"), "let x = 2;\n");
+
+        assert_eq!(entry.model, "gpt-4");
+        assert_eq!(entry.files.len(), 1);
+        assert_eq!(
+            entry.files[0].original_hash,
+            Some(sha256::digest("let x = 1;\n"))
+        );
+        assert_eq!(entry.files[0].hash, Some(sha256::digest("let x = 2;\n")));
+        assert!(entry.files[0].patch.contains("-let x = 1;\n+let x = 2;"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_recorded_for_a_fix_without_changes() {
+        let directory = directory("unchanged");
+        let file = directory.join("main.rs");
+        std::fs::write(&file, "let x = 1;\n").unwrap();
+
+        let mut snapshot = Snapshot::new();
+        snapshot.record(&file.to_string_lossy());
+
+        assert!(Journal::entry(&snapshot, &[], "gpt-4").is_none());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn undo_restores_the_original() {
+        let directory = directory("undo");
+        let file = directory.join("main.rs");
+        let original = "fn main() {\n    let x = 1;\n    println!(x);\n}\n";
+
+        let entry = fix(
+            &file,
+            Some(original),
+            "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n",
+        );
+
+        entry.undo().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), original);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn undo_refuses_when_the_file_changed_since() {
+        let directory = directory("changed");
+        let file = directory.join("main.rs");
+
+        let entry = fix(&file, Some("let x = 1;\n"), "let x = 2;\n");
+        std::fs::write(&file, "let x = 3;\n").unwrap();
+
+        assert_eq!(
+            entry.undo(),
+            Err(UndoError::Changed {
+                file: file.to_string_lossy().to_string()
+            })
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "let x = 3;\n");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn undo_leaves_every_file_alone_when_one_changed() {
+        let directory = directory("partial");
+        let first = directory.join("lib.rs");
+        let second = directory.join("main.rs");
+        std::fs::write(&first, "a\n").unwrap();
+        std::fs::write(&second, "b\n").unwrap();
+
+        let mut snapshot = Snapshot::new();
+        snapshot.record(&first.to_string_lossy());
+        snapshot.record(&second.to_string_lossy());
+
+        std::fs::write(&first, "A\n").unwrap();
+        std::fs::write(&second, "B\n").unwrap();
+
+        let entry = Journal::entry(&snapshot, &[], "gpt-4").unwrap();
+        std::fs::write(&second, "edited\n").unwrap();
+
+        assert!(matches!(entry.undo(), Err(UndoError::Changed { .. })));
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "A\n");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn undoing_a_fix_that_created_a_file_removes_it() {
+        let directory = directory("created");
+        let file = directory.join("helpers.rs");
+
+        let entry = fix(&file, None, "pub fn help() {}\n");
+
+        assert_eq!(entry.files[0].original_hash, None);
+
+        entry.undo().unwrap();
+
+        assert!(!file.exists());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
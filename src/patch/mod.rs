@@ -0,0 +1,7 @@
+pub mod apply;
+pub mod error;
+pub mod git;
+pub mod hunk;
+pub mod journal;
+pub mod review;
+pub mod rollback;
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+use colored::Colorize;
+use dialoguer::console::Term;
+
+use crate::cli::prompts;
+
+use super::hunk::{FilePatch, Hunk, Line};
+
+const ACTIONS: [&str; 5] = [
+    "✅ Accept",
+    "❌ Reject",
+    "✏️  Edit",
+    "⏩ Accept all remaining",
+    "⏹️  Reject all remaining",
+];
+
+/// Walks the user through proposed patches hunk by hunk before anything is written
+pub struct Review {
+    // Accepts every remaining hunk without asking
+    accept_all: bool,
+
+    // Rejects every remaining hunk without asking
+    reject_all: bool,
+}
+
+impl Review {
+    pub fn new(accept_all: bool) -> Self {
+        Self {
+            accept_all,
+            reject_all: false,
+        }
+    }
+
+    /// Returns the patch with only the hunks the user accepted, or `None` if every hunk was rejected.
+    pub fn review(&mut self, patch: &FilePatch) -> Option<FilePatch> {
+        let mut accepted: Vec<Hunk> = Vec::new();
+        let mut queue: VecDeque<Hunk> = patch.hunks.iter().cloned().collect();
+        let mut index = 0;
+
+        while let Some(hunk) = queue.pop_front() {
+            if self.accept_all {
+                accepted.push(hunk);
+                continue;
+            }
+
+            if self.reject_all {
+                continue;
+            }
+
+            index += 1;
+
+            println!("{}", render(&patch.file, index, index + queue.len(), &hunk));
+
+            // There is nobody to ask, so leave the files alone
+            if !Term::stderr().is_term() {
+                println!(
+                    "{} Not running in a terminal, rejecting. Pass `{}` to apply fixes without review.",
+                    ">".bright_black(),
+                    "--yes".bright_cyan()
+                );
+                self.reject_all = true;
+                continue;
+            }
+
+            let selection = prompts::Select {
+                message: "Apply this change?".into(),
+                paged: false,
+                selected: Some(1),
+                items: ACTIONS.iter().map(|action| (*action).into()).collect(),
+            }
+            .run();
+
+            match selection {
+                Ok(0) => accepted.push(hunk),
+                Ok(1) => {}
+                Ok(2) => {
+                    // Edited hunks are shown again so the result can be checked before accepting it
+                    let edited = edit(&patch.file, &hunk).unwrap_or_else(|| vec![hunk]);
+
+                    for hunk in edited.into_iter().rev() {
+                        queue.push_front(hunk);
+                    }
+
+                    index -= 1;
+                }
+                Ok(3) => {
+                    accepted.push(hunk);
+                    self.accept_all = true;
+                }
+                _ => self.reject_all = true,
+            }
+        }
+
+        if accepted.is_empty() {
+            return None;
+        }
+
+        Some(FilePatch {
+            file: patch.file.clone(),
+            hunks: accepted,
+        })
+    }
+}
+
+/// Renders a hunk as a colored unified diff
+pub fn render(file: &str, index: usize, total: usize, hunk: &Hunk) -> String {
+    let location = match hunk.old_start {
+        Some(start) => format!("{}:{}", file, start),
+        None => file.to_string(),
+    };
+
+    let mut output = format!(
+        "{} {} {}\n",
+        "──".bright_black(),
+        location.bright_yellow(),
+        format!("(change {}/{})", index, total).bright_black()
+    );
+
+    for line in &hunk.lines {
+        let rendered = match line {
+            Line::Context(text) => format!("  {}", text).bright_black(),
+            Line::Remove(text) => format!("- {}", text).red(),
+            Line::Add(text) => format!("+ {}", text).green(),
+        };
+
+        output.push_str(&format!("{}\n", rendered));
+    }
+
+    output
+}
+
+// Opens the hunk in the user's editor, returning the edited hunks if they parse
+fn edit(file: &str, hunk: &Hunk) -> Option<Vec<Hunk>> {
+    let edited = prompts::Editor {
+        initial: hunk.to_string().into(),
+        extension: ".diff".into(),
+    }
+    .run();
+
+    match edited {
+        Ok(Some(text)) => match FilePatch::parse(file, &text) {
+            Ok(patch) => Some(patch.hunks),
+            Err(err) => {
+                println!("{} Discarding edit: {}", ">".bright_black(), err);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(err) => {
+            println!("{} Unable to open an editor: {}", ">".bright_black(), err);
+            None
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+
+/// When a fix that was written to disk gets reverted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum RollbackPolicy {
+    /// Keep every fix, even if it breaks the build further
+    #[serde(rename = "never")]
+    Never,
+
+    /// Revert when there are more errors than before the fix
+    #[serde(rename = "more-errors")]
+    MoreErrors,
+
+    /// Revert when any error appears that wasn't there before the fix
+    #[default]
+    #[serde(rename = "new-errors")]
+    NewErrors,
+}
+
+impl RollbackPolicy {
+    /// Whether a fix should be reverted, given the error counts around it and how many errors it introduced.
+    pub fn should_rollback(&self, before: usize, after: usize, introduced: usize) -> bool {
+        match self {
+            Self::Never => false,
+            Self::MoreErrors => after > before,
+            Self::NewErrors => after > before || introduced > 0,
+        }
+    }
+}
+
+/// Contents of files as they were before a fix touched them
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    // `None` means the file didn't exist yet
+    files: LinkedHashMap<String, Option<String>>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the current contents of `file`, unless it has been recorded already.
+    pub fn record(&mut self, file: &str) {
+        self.record_contents(file, std::fs::read_to_string(file).ok());
+    }
+
+    /// Records `contents` as what `file` held before the fix, unless it has been recorded already.
+    pub fn record_contents(&mut self, file: &str, contents: Option<String>) {
+        if self.files.contains_key(file) {
+            return;
+        }
+
+        self.files.insert(file.to_string(), contents);
+    }
+
+    /// Writes every recorded file back, removing files that didn't exist.
+    ///
+    /// A file that can't be restored doesn't stop the others, the ones that failed are returned with the reason.
+    pub fn restore(&self) -> Result<(), Vec<(String, io::Error)>> {
+        let failed: Vec<(String, io::Error)> = self
+            .files
+            .iter()
+            .filter_map(|(file, contents)| {
+                let restored = match contents {
+                    Some(contents) => std::fs::write(file, contents),
+                    None if std::path::Path::new(file).exists() => std::fs::remove_file(file),
+                    None => Ok(()),
+                };
+
+                restored.err().map(|error| (file.clone(), error))
+            })
+            .collect();
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+
+    /// Every recorded file with what it contained before the fix, `None` for files that didn't exist
+    pub fn contents(&self) -> impl Iterator<Item = (&String, &Option<String>)> {
+        self.files.iter()
+    }
+
+    pub fn files(&self) -> Vec<&String> {
+        self.files.keys().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_the_other_files_when_one_fails() {
+        let directory = std::env::temp_dir().join(format!("neura-rollback-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let missing = directory.join("missing").join("lib.rs");
+        let file = directory.join("main.rs");
+        std::fs::write(&file, "before").unwrap();
+
+        let mut snapshot = Snapshot::new();
+        snapshot.files.insert(
+            missing.to_string_lossy().to_string(),
+            Some(String::from("before")),
+        );
+        snapshot.record(&file.to_string_lossy());
+
+        std::fs::write(&file, "after").unwrap();
+
+        let failed = snapshot.restore().unwrap_err();
+
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, missing.to_string_lossy());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "before");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,424 @@
+use async_trait::async_trait;
+use miette::{miette, IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    provider::{Completion, CompletionRequest, Message, Provider, Role, Tool, Usage},
+    sse,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    messages: Vec<&'a Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    max_tokens: u64,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ToolDefinition<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice<'a>>,
+}
+
+#[derive(Serialize)]
+struct ToolDefinition<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolChoice<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'a str,
+}
+
+impl<'a> From<&'a Tool> for ToolDefinition<'a> {
+    fn from(tool: &'a Tool) -> Self {
+        Self {
+            name: &tool.name,
+            description: &tool.description,
+            input_schema: &tool.parameters,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    usage: MessagesUsage,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    // The arguments of a `tool_use` block
+    input: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct MessagesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockDelta {
+        delta: Delta,
+    },
+    MessageDelta {
+        usage: MessagesUsage,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    usage: MessagesUsage,
+}
+
+#[derive(Deserialize)]
+struct Delta {
+    #[serde(default)]
+    text: String,
+    // Tool arguments are streamed as fragments of JSON
+    #[serde(default)]
+    partial_json: String,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+/// Client for the Anthropic Messages API
+pub struct Anthropic {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    tools: bool,
+}
+
+impl Anthropic {
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            tools: true,
+        }
+    }
+
+    /// Turns tool use on or off, for models that predate it
+    pub fn with_tools(mut self, tools: bool) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response> {
+        // System prompts are passed separately from the conversation
+        let system = request
+            .messages
+            .iter()
+            .filter(|message| message.role == Role::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<&str>>();
+
+        let tool = request.tool.as_ref().filter(|_| self.tools);
+
+        let body = MessagesRequest {
+            model: &self.model,
+            messages: request
+                .messages
+                .iter()
+                .filter(|message| message.role != Role::System)
+                .collect(),
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream: stream.then_some(true),
+            tools: tool.map(|tool| vec![ToolDefinition::from(tool)]),
+            // The model is made to use the tool rather than answer in prose
+            tool_choice: tool.map(|tool| ToolChoice {
+                kind: "tool",
+                name: &tool.name,
+            }),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .into_diagnostic()?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+
+            return Err(miette!("{} returned {}: {}", self.base_url, status, body));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Provider for Anthropic {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        let response: MessagesResponse = self
+            .send(request, false)
+            .await?
+            .json()
+            .await
+            .into_diagnostic()?;
+
+        // A tool call's arguments are the answer, any text next to them is commentary
+        let content = match response
+            .content
+            .iter()
+            .find_map(|block| block.input.as_ref().filter(|_| block.kind == "tool_use"))
+        {
+            Some(input) => input.to_string(),
+            None => response
+                .content
+                .into_iter()
+                .filter(|block| block.kind == "text")
+                .map(|block| block.text)
+                .collect::<String>(),
+        };
+
+        Ok(Completion {
+            content,
+            usage: Some(Usage {
+                prompt_tokens: response.usage.input_tokens,
+                completion_tokens: response.usage.output_tokens,
+            }),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion> {
+        let response = self.send(request, true).await?;
+
+        let mut content = String::new();
+        let mut usage = Usage::default();
+
+        sse::read_events(response, |data| {
+            match serde_json::from_str::<StreamEvent>(data).into_diagnostic()? {
+                StreamEvent::MessageStart { message } => {
+                    usage.prompt_tokens = message.usage.input_tokens;
+                }
+                StreamEvent::ContentBlockDelta { delta } => {
+                    for token in [&delta.text, &delta.partial_json] {
+                        if token.is_empty() {
+                            continue;
+                        }
+
+                        on_token(token);
+                        content.push_str(token);
+                    }
+                }
+                StreamEvent::MessageDelta { usage: delta } => {
+                    usage.completion_tokens = delta.output_tokens;
+                }
+                StreamEvent::Error { error } => {
+                    return Err(miette!(
+                        "{} returned an error: {}",
+                        self.base_url,
+                        error.message
+                    ));
+                }
+                StreamEvent::Other => {}
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(Completion {
+            content,
+            usage: Some(usage),
+        })
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.tools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::providers::mock::{events, serve};
+
+    fn request(tool: bool) -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![Message::system("be brief"), Message::user("fix it")],
+            max_tokens: 100,
+            temperature: 0.2,
+            tool: tool.then(|| Tool {
+                name: String::from("propose_changes"),
+                description: String::from("Proposes changes"),
+                parameters: json!({"type": "object"}),
+            }),
+        }
+    }
+
+    fn anthropic(base_url: String) -> Anthropic {
+        Anthropic::new(
+            base_url,
+            String::from("key"),
+            String::from("claude-3-5-sonnet-20241022"),
+        )
+    }
+
+    #[tokio::test]
+    async fn sends_a_messages_request_and_reads_the_tool_use() {
+        let response = json!({
+            "content": [
+                {"type": "text", "text": "Here you go"},
+                {"type": "tool_use", "id": "toolu_1", "name": "propose_changes", "input": {"changes": []}}
+            ],
+            "usage": {"input_tokens": 12, "output_tokens": 5}
+        });
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let completion = anthropic(base_url).complete(&request(true)).await.unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.path, "/v1/messages");
+        assert_eq!(sent.header("x-api-key"), Some("key"));
+        assert_eq!(sent.header("anthropic-version"), Some(API_VERSION));
+        assert_eq!(
+            sent.body,
+            json!({
+                "model": "claude-3-5-sonnet-20241022",
+                "messages": [{"role": "user", "content": "fix it"}],
+                "system": "be brief",
+                "max_tokens": 100,
+                "temperature": 0.2,
+                "tools": [{
+                    "name": "propose_changes",
+                    "description": "Proposes changes",
+                    "input_schema": {"type": "object"}
+                }],
+                "tool_choice": {"type": "tool", "name": "propose_changes"}
+            })
+        );
+
+        assert_eq!(completion.content, r#"{"changes":[]}"#);
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 5
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_a_text_answer() {
+        let response = json!({
+            "content": [{"type": "text", "text": "Hel"}, {"type": "text", "text": "lo"}],
+            "usage": {"input_tokens": 12, "output_tokens": 2}
+        });
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let completion = anthropic(base_url)
+            .with_tools(false)
+            .complete(&request(true))
+            .await
+            .unwrap();
+        let sent = server.join().unwrap();
+
+        assert!(sent.body.get("tools").is_none());
+        assert!(sent.body.get("tool_choice").is_none());
+        assert_eq!(completion.content, "Hello");
+    }
+
+    #[tokio::test]
+    async fn streams_text_and_tool_input() {
+        let body = events(&[
+            r#"{"type": "message_start", "message": {"usage": {"input_tokens": 12, "output_tokens": 1}}}"#,
+            r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}"#,
+            r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}"#,
+            r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}}"#,
+            r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{}"}}"#,
+            r#"{"type": "ping"}"#,
+            r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 3}}"#,
+            r#"{"type": "message_stop"}"#,
+        ]);
+        let (base_url, server) = serve(200, "text/event-stream", &body);
+
+        let mut tokens: Vec<String> = Vec::new();
+        let completion = anthropic(base_url)
+            .stream(&request(false), &mut |token: &str| {
+                tokens.push(token.to_string())
+            })
+            .await
+            .unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.body["stream"], json!(true));
+
+        assert_eq!(tokens, vec!["Hel", "lo", "{}"]);
+        assert_eq!(completion.content, "Hello{}");
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_errors_in_the_stream() {
+        let body = events(&[
+            r#"{"type": "message_start", "message": {"usage": {"input_tokens": 12, "output_tokens": 1}}}"#,
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#,
+        ]);
+        let (base_url, server) = serve(200, "text/event-stream", &body);
+
+        let error = anthropic(base_url)
+            .stream(&request(false), &mut |_: &str| {})
+            .await
+            .unwrap_err();
+        server.join().unwrap();
+
+        assert!(error.to_string().contains("Overloaded"));
+    }
+}
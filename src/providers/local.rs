@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use miette::Result;
+
+use super::{
+    openai::OpenAi,
+    provider::{Completion, CompletionRequest, Provider},
+};
+
+// Ollama's OpenAI-compatible endpoint, the most common local setup
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434/v1";
+
+/// Client for a self-hosted, OpenAI-compatible server (Ollama, llama.cpp server, vLLM, ...)
+pub struct Local {
+    inner: OpenAi,
+}
+
+impl Local {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            inner: OpenAi::compatible(base_url, api_key, model),
+        }
+    }
+
+    /// Turns function calling on for models that support it, most local models don't
+    pub fn with_tools(mut self, tools: bool) -> Self {
+        self.inner = self.inner.with_tools(tools);
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for Local {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        self.inner.complete(request).await
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion> {
+        self.inner.stream(request, on_token).await
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::providers::{
+        mock::{events, serve},
+        provider::{Message, Tool, Usage},
+    };
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![Message::user("fix it")],
+            max_tokens: 100,
+            temperature: 0.2,
+            tool: Some(Tool {
+                name: String::from("propose_changes"),
+                description: String::from("Proposes changes"),
+                parameters: json!({"type": "object"}),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_a_plain_chat_request_without_a_key() {
+        let response = json!({
+            "choices": [{"message": {"role": "assistant", "content": "Hello"}}],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 2}
+        });
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let local = Local::new(base_url, None, String::from("llama3"));
+        let completion = local.complete(&request()).await.unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.path, "/v1/chat/completions");
+        assert_eq!(sent.header("authorization"), None);
+        assert_eq!(
+            sent.body,
+            json!({
+                "model": "llama3",
+                "messages": [{"role": "user", "content": "fix it"}],
+                "max_tokens": 100,
+                "temperature": 0.2
+            })
+        );
+
+        assert_eq!(completion.content, "Hello");
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 2
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_without_openai_extensions() {
+        let body = events(&[
+            r#"{"choices": [{"delta": {"content": "Hel"}}]}"#,
+            r#"{"choices": [{"delta": {"content": "lo"}, "finish_reason": "stop"}]}"#,
+            "[DONE]",
+        ]);
+        let (base_url, server) = serve(200, "text/event-stream", &body);
+
+        let local = Local::new(base_url, Some(String::from("key")), String::from("llama3"));
+        let mut tokens: Vec<String> = Vec::new();
+        let completion = local
+            .stream(&request(), &mut |token: &str| {
+                tokens.push(token.to_string())
+            })
+            .await
+            .unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.header("authorization"), Some("Bearer key"));
+        assert_eq!(sent.body["stream"], json!(true));
+        assert!(sent.body.get("stream_options").is_none());
+
+        assert_eq!(tokens, vec!["Hel", "lo"]);
+        assert_eq!(completion.content, "Hello");
+        assert_eq!(completion.usage, None);
+    }
+
+    #[tokio::test]
+    async fn sends_the_tool_when_turned_on() {
+        let response = json!({
+            "choices": [{"message": {"tool_calls": [{"function": {"arguments": "{}"}}]}}]
+        });
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let local = Local::new(base_url, None, String::from("llama3")).with_tools(true);
+        let completion = local.complete(&request()).await.unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(
+            sent.body["tools"][0]["function"]["name"],
+            json!("propose_changes")
+        );
+        assert_eq!(completion.content, "{}");
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread::JoinHandle,
+};
+
+/// A request as the mock server received it
+pub struct Request {
+    pub path: String,
+
+    /// Names are lowercase
+    pub headers: Vec<(String, String)>,
+
+    pub body: serde_json::Value,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers a single request on a free local port with `body`, returning the base URL to send it to
+/// and a handle that gives back the request once it has been answered
+pub fn serve(status: u16, content_type: &str, body: &str) -> (String, JoinHandle<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+
+        let mut headers = Vec::new();
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+
+            headers.push((name.to_lowercase(), value.trim().to_string()));
+        }
+
+        let length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(0);
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        stream.write_all(response.as_bytes()).unwrap();
+
+        Request {
+            path: request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string(),
+            headers,
+            body: serde_json::from_slice(&body).unwrap(),
+        }
+    });
+
+    (base_url, handle)
+}
+
+/// Server-sent events carrying each of `events` as data, the way the APIs stream completions
+pub fn events(events: &[&str]) -> String {
+    events
+        .iter()
+        .map(|event| format!("data: {}\n\n", event))
+        .collect()
+}
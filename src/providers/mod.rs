@@ -0,0 +1,71 @@
+pub mod anthropic;
+pub mod local;
+#[cfg(test)]
+mod mock;
+pub mod openai;
+pub mod provider;
+pub mod sse;
+
+use miette::{miette, Result};
+
+use crate::config::Config;
+
+use self::{
+    anthropic::Anthropic,
+    local::Local,
+    openai::OpenAi,
+    provider::{Provider, ProviderKind},
+};
+
+/// Creates the provider selected in `neura.toml`.
+pub fn from_config(config: &Config) -> Result<Box<dyn Provider>> {
+    let model = config
+        .model
+        .ok_or_else(|| miette!("No model configured, run `neura init` first"))?;
+
+    let settings = config.provider.clone().unwrap_or_default();
+
+    let kind = settings.kind.unwrap_or_else(|| model.provider());
+    let model_code = settings.model.unwrap_or_else(|| model.code());
+
+    let api_key_env = settings
+        .api_key_env
+        .unwrap_or_else(|| String::from("NEURA_API_KEY"));
+    let api_key = std::env::var(&api_key_env).ok();
+
+    let provider: Box<dyn Provider> = match kind {
+        ProviderKind::OpenAi => Box::new(
+            OpenAi::new(
+                settings
+                    .base_url
+                    .unwrap_or_else(|| openai::DEFAULT_BASE_URL.to_string()),
+                Some(api_key.ok_or_else(|| miette!("`{}` is not set", api_key_env))?),
+                model_code,
+            )
+            .with_tools(settings.tools.unwrap_or_else(|| model.supports_tools())),
+        ),
+        ProviderKind::Anthropic => Box::new(
+            Anthropic::new(
+                settings
+                    .base_url
+                    .unwrap_or_else(|| anthropic::DEFAULT_BASE_URL.to_string()),
+                api_key.ok_or_else(|| miette!("`{}` is not set", api_key_env))?,
+                model_code,
+            )
+            .with_tools(settings.tools.unwrap_or_else(|| model.supports_tools())),
+        ),
+        // Local servers usually don't require a key, so a missing one is fine
+        ProviderKind::Local => Box::new(
+            Local::new(
+                settings
+                    .base_url
+                    .unwrap_or_else(|| local::DEFAULT_BASE_URL.to_string()),
+                api_key,
+                model_code,
+            )
+            .with_tools(settings.tools.unwrap_or(false)),
+        ),
+    };
+
+    Ok(provider)
+}
@@ -0,0 +1,425 @@
+use async_trait::async_trait;
+use miette::{miette, IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    provider::{Completion, CompletionRequest, Message, Provider, Tool, Usage},
+    sse,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    max_tokens: u64,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ChatTool<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChatTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ChatFunction<'a>,
+}
+
+#[derive(Serialize)]
+struct ChatFunction<'a> {
+    name: &'a str,
+    description: &'a str,
+    parameters: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolChoice<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ToolChoiceFunction<'a>,
+}
+
+#[derive(Serialize)]
+struct ToolChoiceFunction<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    function: FunctionCall,
+}
+
+#[derive(Deserialize)]
+struct FunctionCall {
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+// Tool call arguments are streamed as fragments of a JSON string
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    function: Option<FunctionCall>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+impl From<ChatUsage> for Usage {
+    fn from(usage: ChatUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+impl<'a> From<&'a Tool> for ChatTool<'a> {
+    fn from(tool: &'a Tool) -> Self {
+        Self {
+            kind: "function",
+            function: ChatFunction {
+                name: &tool.name,
+                description: &tool.description,
+                parameters: &tool.parameters,
+            },
+        }
+    }
+}
+
+/// Client for the OpenAI chat completions API, or any server that mirrors it
+pub struct OpenAi {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    // Whether the server understands OpenAI-only extensions such as `stream_options`
+    extensions: bool,
+    // Whether the model supports function calling
+    tools: bool,
+}
+
+impl OpenAi {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            extensions: true,
+            tools: true,
+        }
+    }
+
+    /// Creates a client that sticks to the subset of the API that compatible servers implement.
+    pub fn compatible(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            extensions: false,
+            tools: false,
+            ..Self::new(base_url, api_key, model)
+        }
+    }
+
+    /// Turns function calling on or off, for models that don't match their server's defaults
+    pub fn with_tools(mut self, tools: bool) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response> {
+        let tool = request.tool.as_ref().filter(|_| self.tools);
+
+        let body = ChatRequest {
+            model: &self.model,
+            messages: &request.messages,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream: stream.then_some(true),
+            stream_options: (stream && self.extensions).then_some(StreamOptions {
+                include_usage: true,
+            }),
+            tools: tool.map(|tool| vec![ChatTool::from(tool)]),
+            // The model is made to call the function rather than answer in prose
+            tool_choice: tool.map(|tool| ToolChoice {
+                kind: "function",
+                function: ToolChoiceFunction { name: &tool.name },
+            }),
+        };
+
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response = builder.send().await.into_diagnostic()?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+
+            return Err(miette!("{} returned {}: {}", self.base_url, status, body));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAi {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        let response: ChatResponse = self
+            .send(request, false)
+            .await?
+            .json()
+            .await
+            .into_diagnostic()?;
+
+        let message = response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message)
+            .ok_or_else(|| miette!("{} returned no choices", self.base_url))?;
+
+        // A function call's arguments are the answer, any text next to them is commentary
+        let content = match message.tool_calls.into_iter().next() {
+            Some(call) => call.function.arguments,
+            None => message.content.unwrap_or_default(),
+        };
+
+        Ok(Completion {
+            content,
+            usage: response.usage.map(Usage::from),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion> {
+        let response = self.send(request, true).await?;
+
+        let mut content = String::new();
+        let mut usage = None;
+
+        sse::read_events(response, |data| {
+            if data == "[DONE]" {
+                return Ok(());
+            }
+
+            let chunk: ChatChunk = serde_json::from_str(data).into_diagnostic()?;
+
+            for choice in chunk.choices {
+                let arguments = choice
+                    .delta
+                    .tool_calls
+                    .into_iter()
+                    .filter_map(|call| call.function)
+                    .map(|function| function.arguments);
+
+                for token in choice.delta.content.into_iter().chain(arguments) {
+                    on_token(&token);
+                    content.push_str(&token);
+                }
+            }
+
+            if let Some(chunk_usage) = chunk.usage {
+                usage = Some(Usage::from(chunk_usage));
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(Completion { content, usage })
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.tools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::providers::mock::{events, serve};
+
+    fn request(tool: bool) -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![Message::system("be brief"), Message::user("fix it")],
+            max_tokens: 100,
+            temperature: 0.2,
+            tool: tool.then(|| Tool {
+                name: String::from("propose_changes"),
+                description: String::from("Proposes changes"),
+                parameters: json!({"type": "object"}),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_a_chat_request_and_reads_the_function_call() {
+        let response = json!({
+            "choices": [{"message": {
+                "content": null,
+                "tool_calls": [{"function": {"name": "propose_changes", "arguments": "{\"changes\": []}"}}]
+            }}],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 5}
+        });
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let openai = OpenAi::new(base_url, Some(String::from("key")), String::from("gpt-4"));
+        let completion = openai.complete(&request(true)).await.unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.path, "/v1/chat/completions");
+        assert_eq!(sent.header("authorization"), Some("Bearer key"));
+        assert_eq!(
+            sent.body,
+            json!({
+                "model": "gpt-4",
+                "messages": [
+                    {"role": "system", "content": "be brief"},
+                    {"role": "user", "content": "fix it"}
+                ],
+                "max_tokens": 100,
+                "temperature": 0.2,
+                "tools": [{"type": "function", "function": {
+                    "name": "propose_changes",
+                    "description": "Proposes changes",
+                    "parameters": {"type": "object"}
+                }}],
+                "tool_choice": {"type": "function", "function": {"name": "propose_changes"}}
+            })
+        );
+
+        assert_eq!(completion.content, "{\"changes\": []}");
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 5
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_a_text_answer() {
+        let response = json!({"choices": [{"message": {"content": "Hello"}}]});
+        let (base_url, server) = serve(200, "application/json", &response.to_string());
+
+        let openai = OpenAi::new(base_url, Some(String::from("key")), String::from("gpt-4"))
+            .with_tools(false);
+        let completion = openai.complete(&request(true)).await.unwrap();
+        let sent = server.join().unwrap();
+
+        assert!(sent.body.get("tools").is_none());
+        assert_eq!(completion.content, "Hello");
+        assert_eq!(completion.usage, None);
+    }
+
+    #[tokio::test]
+    async fn streams_text_and_function_arguments() {
+        let body = events(&[
+            r#"{"choices": [{"delta": {"role": "assistant", "content": "Hel"}}]}"#,
+            r#"{"choices": [{"delta": {"content": "lo"}}]}"#,
+            r#"{"choices": [{"delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{}"}}]}}]}"#,
+            r#"{"choices": [], "usage": {"prompt_tokens": 12, "completion_tokens": 3}}"#,
+            "[DONE]",
+        ]);
+        let (base_url, server) = serve(200, "text/event-stream", &body);
+
+        let openai = OpenAi::new(base_url, Some(String::from("key")), String::from("gpt-4"));
+        let mut tokens: Vec<String> = Vec::new();
+        let completion = openai
+            .stream(&request(false), &mut |token: &str| {
+                tokens.push(token.to_string())
+            })
+            .await
+            .unwrap();
+        let sent = server.join().unwrap();
+
+        assert_eq!(sent.body["stream"], json!(true));
+        assert_eq!(sent.body["stream_options"], json!({"include_usage": true}));
+
+        assert_eq!(tokens, vec!["Hel", "lo", "{}"]);
+        assert_eq!(completion.content, "Hello{}");
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_errors_with_the_status_and_body() {
+        let (base_url, server) = serve(401, "application/json", r#"{"error": "bad key"}"#);
+
+        let openai = OpenAi::new(base_url, Some(String::from("key")), String::from("gpt-4"));
+        let error = openai.complete(&request(false)).await.unwrap_err();
+        server.join().unwrap();
+
+        assert!(error.to_string().contains("401"));
+        assert!(error.to_string().contains("bad key"));
+    }
+}
@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use miette::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+
+// Building the tokenizer is expensive, so it is only done once per session
+static BPE: Lazy<CoreBPE> = Lazy::new(|| tiktoken_rs::cl100k_base().unwrap());
+
+/// Backend that completions are requested from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProviderKind {
+    #[serde(rename = "openai")]
+    OpenAi,
+    #[serde(rename = "anthropic")]
+    Anthropic,
+    #[serde(rename = "local")]
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// A function the model is made to call, so its answer comes back as structured arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+
+    /// JSON schema of the arguments
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub messages: Vec<Message>,
+    pub max_tokens: u64,
+    pub temperature: f32,
+
+    /// When set, the model has to answer by calling this tool and the completion holds its arguments as JSON.
+    /// Only honored by providers that support tools.
+    pub tool: Option<Tool>,
+}
+
+/// Token usage as reported by the provider
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub content: String,
+    pub usage: Option<Usage>,
+}
+
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Requests a completion and waits for the whole response.
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion>;
+
+    /// Requests a completion, calling `on_token` with every chunk of text as it arrives.
+    ///
+    /// The returned completion contains the full response once the stream ends.
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<Completion>;
+
+    /// Whether the provider can be made to answer through a tool call
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Counts the tokens `text` takes up for this provider's model.
+    ///
+    /// Defaults to the `cl100k_base` encoding, which is exact for OpenAI models and a close estimate for others.
+    fn count_tokens(&self, text: &str) -> usize {
+        BPE.encode_with_special_tokens(text).len()
+    }
+}
@@ -0,0 +1,28 @@
+use futures_util::StreamExt;
+use miette::{IntoDiagnostic, Result};
+
+/// Reads a server-sent events response, calling `on_data` with the payload of every `data:` line.
+pub async fn read_events(
+    response: reqwest::Response,
+    mut on_data: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut stream = response.bytes_stream();
+
+    // Chunks can end in the middle of a line (or a multi-byte character), so bytes are buffered until a newline
+    let mut buffer: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = stream.next().await {
+        buffer.extend_from_slice(&chunk.into_diagnostic()?);
+
+        while let Some(index) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=index).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line);
+
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                on_data(data.trim_start())?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::{
+    diagnostics::diagnostic::{Diagnostic, Span},
+    fixer::context::rust_files,
+};
+
+static CURRENT: Lazy<Workspace> = Lazy::new(Workspace::load);
+
+/// The relevant parts of `cargo metadata --no-deps`
+#[derive(Deserialize)]
+struct Metadata {
+    workspace_root: PathBuf,
+    target_directory: PathBuf,
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    name: String,
+    manifest_path: PathBuf,
+}
+
+/// A package that is a member of the workspace
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+
+    /// The directory its `Cargo.toml` is in
+    pub root: PathBuf,
+}
+
+/// The Cargo workspace neura runs in.
+///
+/// rustc reports paths relative to the workspace root, and the only files neura edits are
+/// those of its member packages, never registry sources, vendored crates or build output.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub target_directory: PathBuf,
+    pub members: Vec<Package>,
+}
+
+impl Workspace {
+    /// The workspace of the current directory, asked from `cargo metadata` once
+    pub fn current() -> &'static Workspace {
+        &CURRENT
+    }
+
+    // Outside of a workspace, e.g. when the manifest is broken, the current directory is treated as a single package
+    fn load() -> Self {
+        let cwd = cwd();
+
+        let metadata = Command::new("cargo")
+            .args(["metadata", "--no-deps", "--format-version", "1"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| serde_json::from_slice::<Metadata>(&output.stdout).ok());
+
+        let Some(metadata) = metadata else {
+            return Self {
+                target_directory: cwd.join("target"),
+                members: vec![Package {
+                    name: String::new(),
+                    root: cwd.clone(),
+                }],
+                root: cwd,
+            };
+        };
+
+        Self {
+            root: metadata.workspace_root,
+            target_directory: metadata.target_directory,
+            members: metadata
+                .packages
+                .into_iter()
+                .filter_map(|package| {
+                    Some(Package {
+                        name: package.name,
+                        root: package.manifest_path.parent()?.to_path_buf(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// The member package called `name`
+    pub fn package(&self, name: &str) -> Option<&Package> {
+        self.members.iter().find(|package| package.name == name)
+    }
+
+    /// Turns a path reported by rustc, which is relative to the workspace root, into one
+    /// that can be opened from the current directory. Paths in the current directory stay relative.
+    pub fn resolve(&self, file: &str) -> String {
+        display(&normalize(&self.root.join(file)))
+    }
+
+    /// Points every span of `diagnostic`, and of its children and macro expansions, at files
+    /// that can be opened from the current directory
+    pub fn resolve_spans(&self, diagnostic: &mut Diagnostic) {
+        fn resolve_span(workspace: &Workspace, span: &mut Span) {
+            span.file_name = workspace.resolve(&span.file_name);
+
+            if let Some(expansion) = span.expansion.as_mut() {
+                resolve_span(workspace, &mut expansion.span);
+
+                if let Some(def_site) = expansion.def_site_span.as_mut() {
+                    resolve_span(workspace, def_site);
+                }
+            }
+        }
+
+        for span in diagnostic.spans.iter_mut() {
+            resolve_span(self, span);
+        }
+
+        for child in diagnostic.children.iter_mut() {
+            self.resolve_spans(child);
+        }
+    }
+
+    /// The member package a file belongs to, `None` for files neura must not edit.
+    ///
+    /// A file belongs to the package of the closest `Cargo.toml` above it, so vendored crates and
+    /// registry sources are never mistaken for the member they sit in. The file doesn't have to exist yet.
+    pub fn member_of(&self, file: &str) -> Option<&Package> {
+        let path = normalize(&cwd().join(file));
+
+        if path.starts_with(&self.target_directory) {
+            return None;
+        }
+
+        let manifest_dir = path
+            .ancestors()
+            .skip(1)
+            .find(|directory| directory.join("Cargo.toml").is_file())?;
+
+        self.members
+            .iter()
+            .find(|package| normalize(&package.root) == manifest_dir)
+    }
+
+    /// Whether neura may write to `file`
+    pub fn is_editable(&self, file: &str) -> bool {
+        self.member_of(file).is_some()
+    }
+
+    /// Every `.rs` file of the member packages, as paths that can be opened from the current directory
+    pub fn rust_files(&self) -> Vec<String> {
+        rust_files(&self.root)
+            .into_iter()
+            .map(|path| display(&normalize(&path)))
+            .filter(|file| self.is_editable(file))
+            .collect()
+    }
+}
+
+// Removes `.` and `..` from an absolute path without touching the filesystem, so it works for files that don't exist
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+// The current directory with symlinks resolved, the way `cargo metadata` reports paths
+fn cwd() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+
+    std::fs::canonicalize(&cwd).unwrap_or(cwd)
+}
+
+/// The path relative to the current directory when it's inside it, otherwise absolute
+pub fn display(path: &Path) -> String {
+    match path.strip_prefix(cwd()) {
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => path.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A workspace with a single member in a fresh directory, with a vendored crate inside it
+    fn workspace(name: &str) -> Workspace {
+        let root =
+            std::env::temp_dir().join(format!("neura-workspace-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("vendor/dep/src")).unwrap();
+        std::fs::write(root.join("Cargo.toml"), "").unwrap();
+        std::fs::write(root.join("vendor/dep/Cargo.toml"), "").unwrap();
+
+        let root = std::fs::canonicalize(root).unwrap();
+
+        Workspace {
+            target_directory: root.join("target"),
+            members: vec![Package {
+                name: String::from("app"),
+                root: root.clone(),
+            }],
+            root,
+        }
+    }
+
+    #[test]
+    fn normalizes_parent_and_current_directories() {
+        assert_eq!(
+            normalize(Path::new("/work/app/./src/../tests/it.rs")),
+            PathBuf::from("/work/app/tests/it.rs")
+        );
+        assert_eq!(
+            normalize(Path::new("/work/app/src/bin/../../build.rs")),
+            PathBuf::from("/work/app/build.rs")
+        );
+        assert_eq!(normalize(Path::new("/../src")), PathBuf::from("/src"));
+    }
+
+    #[test]
+    fn files_of_a_member_belong_to_it() {
+        let workspace = workspace("member");
+        let root = workspace.root.clone();
+
+        let absolute = root.join("src/main.rs");
+        let roundabout = root.join("src/../src/new.rs");
+
+        assert_eq!(
+            workspace
+                .member_of(&absolute.to_string_lossy())
+                .map(|package| package.name.as_str()),
+            Some("app")
+        );
+        assert_eq!(
+            workspace
+                .member_of(&roundabout.to_string_lossy())
+                .map(|package| package.name.as_str()),
+            Some("app")
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn vendored_crates_inside_a_member_are_not_editable() {
+        let workspace = workspace("vendored");
+        let root = workspace.root.clone();
+
+        assert!(!workspace.is_editable(&root.join("vendor/dep/src/lib.rs").to_string_lossy()));
+        assert!(
+            !workspace.is_editable(&root.join("src/../vendor/dep/src/lib.rs").to_string_lossy())
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn build_output_is_not_editable() {
+        let workspace = workspace("target");
+        let root = workspace.root.clone();
+
+        assert!(!workspace.is_editable(
+            &root
+                .join("target/debug/build/app/out/generated.rs")
+                .to_string_lossy()
+        ));
+        assert!(!workspace.is_editable(&root.join("src/../target/generated.rs").to_string_lossy()));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn files_outside_every_member_are_not_editable() {
+        let workspace = workspace("outside");
+        let root = workspace.root.clone();
+
+        assert!(!workspace.is_editable(&root.join("../elsewhere/src/lib.rs").to_string_lossy()));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}